@@ -0,0 +1,190 @@
+use flate2::write::GzEncoder;
+use git2::{ObjectType, Repository, Tree};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use super::util;
+
+/// Archive file being written, in the format given by the file extension
+pub enum Archive {
+    Tar(tar::Builder<File>),
+    TarGz(tar::Builder<GzEncoder<File>>),
+    Zip(zip::ZipWriter<File>),
+}
+
+impl Archive {
+    /// Create an archive file, as .tar, .tar.gz, .tgz or .zip
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow::anyhow!("invalid archive name {:?}", path))?;
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            let encoder = GzEncoder::new(File::create(path)?, flate2::Compression::default());
+            Ok(Archive::TarGz(tar::Builder::new(encoder)))
+        } else if name.ends_with(".tar") {
+            Ok(Archive::Tar(tar::Builder::new(File::create(path)?)))
+        } else if name.ends_with(".zip") {
+            Ok(Archive::Zip(zip::ZipWriter::new(File::create(path)?)))
+        } else {
+            anyhow::bail!(
+                "unknown archive format {}, expecting .tar, .tar.gz, .tgz or .zip",
+                name
+            )
+        }
+    }
+
+    /// Add a file, `mtime` being in seconds since the epoch
+    pub fn add_file(
+        &mut self,
+        path: &str,
+        mode: u32,
+        mtime: i64,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        match self {
+            Archive::Tar(builder) => tar_add_file(builder, path, mode, mtime, data),
+            Archive::TarGz(builder) => tar_add_file(builder, path, mode, mtime, data),
+            Archive::Zip(writer) => {
+                writer.start_file(path, zip_options(mode, mtime))?;
+                writer.write_all(data)?;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn add_symlink(&mut self, path: &str, target: &str, mtime: i64) -> anyhow::Result<()> {
+        match self {
+            Archive::Tar(builder) => tar_add_symlink(builder, path, target, mtime),
+            Archive::TarGz(builder) => tar_add_symlink(builder, path, target, mtime),
+            Archive::Zip(writer) => {
+                writer.add_symlink(path, target, zip_options(0o777, mtime))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Add the content of a git tree under a directory.
+    ///
+    /// With `submodules` set to the working directory of the repository, the
+    /// submodules are added too, from the repositories checked out there.
+    pub fn add_tree(
+        &mut self,
+        repo: &Repository,
+        tree: &Tree,
+        prefix: &str,
+        mtime: i64,
+        submodules: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        for entry in tree.iter() {
+            let name = entry
+                .name()
+                .ok_or_else(|| anyhow::anyhow!("non unicode path in {}", prefix))?;
+            let path = format!("{}/{}", prefix, name);
+            match entry.kind() {
+                Some(ObjectType::Tree) => {
+                    let subtree = repo.find_tree(entry.id())?;
+                    let workdir = submodules.map(|dir| dir.join(name));
+                    self.add_tree(repo, &subtree, &path, mtime, workdir.as_deref())?
+                }
+                Some(ObjectType::Blob) => {
+                    let blob = repo.find_blob(entry.id())?;
+                    match entry.filemode() {
+                        0o120000 => {
+                            let target = std::str::from_utf8(blob.content())?;
+                            self.add_symlink(&path, target, mtime)?
+                        }
+                        0o100755 => self.add_file(&path, 0o755, mtime, blob.content())?,
+                        _ => self.add_file(&path, 0o644, mtime, blob.content())?,
+                    }
+                }
+                Some(ObjectType::Commit) => {
+                    if let Some(dir) = submodules {
+                        let sub_dir = dir.join(name);
+                        let sub_repo = Repository::open(&sub_dir).map_err(|e| {
+                            anyhow::anyhow!("submodule {} not checked out: {}", path, e)
+                        })?;
+                        let commit = sub_repo.find_commit(entry.id()).map_err(|_| {
+                            anyhow::anyhow!("submodule {} commit {} not fetched", path, entry.id())
+                        })?;
+                        let sub_tree = commit.tree()?;
+                        self.add_tree(&sub_repo, &sub_tree, &path, mtime, Some(&sub_dir))?
+                    }
+                }
+                _ => anyhow::bail!("unexpected object at {}", path),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Archive::Tar(builder) => {
+                builder.into_inner()?.flush()?;
+            }
+            Archive::TarGz(builder) => {
+                builder.into_inner()?.finish()?.flush()?;
+            }
+            Archive::Zip(mut writer) => {
+                writer.finish()?.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn tar_add_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    mode: u32,
+    mtime: i64,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(data.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(mtime.max(0) as u64);
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+fn tar_add_symlink<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    target: &str,
+    mtime: i64,
+) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    header.set_mode(0o777);
+    header.set_mtime(mtime.max(0) as u64);
+    builder.append_link(&mut header, path, target)?;
+    Ok(())
+}
+
+fn zip_options(mode: u32, mtime: i64) -> zip::write::FileOptions {
+    let options = zip::write::FileOptions::default().unix_permissions(mode);
+    match zip_datetime(mtime) {
+        Some(datetime) => options.last_modified_time(datetime),
+        None => options,
+    }
+}
+
+/// Convert seconds since the epoch to a zip (MS-DOS) UTC date, which only covers 1980 to 2107
+fn zip_datetime(mtime: i64) -> Option<zip::DateTime> {
+    let (year, month, day) = util::civil_from_days(mtime.div_euclid(86400));
+    let secs = mtime.rem_euclid(86400);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month as u8,
+        day as u8,
+        (secs / 3600) as u8,
+        (secs % 3600 / 60) as u8,
+        (secs % 60) as u8,
+    )
+    .ok()
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+/// Name of the index file written along the bundles
+pub const BUNDLE_INDEX_NAME: &str = "mugit-bundles.toml";
+
+/// Description of a directory of bundles, one per repository
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BundleIndex {
+    /// tag or lockfile the bundles are incremental from, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    /// bundles by checkout directory of the repository
+    #[serde(default)]
+    pub repos: BTreeMap<String, BundleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleEntry {
+    /// bundle file, relative to the index
+    pub bundle: String,
+    /// remote the bundled remote branches were read from
+    #[serde(rename = "remote-name")]
+    pub remote_name: String,
+    /// default branch to checkout when cloning
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+    /// commits the bundle needs to be applied, empty for a full bundle
+    #[serde(default)]
+    pub prerequisites: Vec<String>,
+}
+
+impl BundleIndex {
+    pub fn from_dir(dir: &Path) -> anyhow::Result<BundleIndex> {
+        let content = std::fs::read_to_string(dir.join(BUNDLE_INDEX_NAME))?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn write(&self, dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(dir.join(BUNDLE_INDEX_NAME), toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Result of creating a bundle
+pub enum Created {
+    Written,
+    /// nothing new since the prerequisites
+    Empty,
+}
+
+// libgit2 can neither write nor read bundles, so these always use the git executable
+
+/// Bundle the branches of a remote and all the tags, excluding the history of the prerequisites
+pub fn create(
+    git_dir: &Path,
+    bundle: &Path,
+    remote_name: &str,
+    prerequisites: &[String],
+) -> anyhow::Result<Created> {
+    if let Some(parent) = bundle.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut command = Command::new("git");
+    command
+        .arg(format!("--git-dir={}", git_dir.to_str().unwrap()))
+        .arg("bundle")
+        .arg("create")
+        .arg(bundle.to_str().expect("bundle path is utf8"))
+        .arg(format!("--remotes={}", remote_name))
+        .arg("--tags");
+    for commit in prerequisites {
+        command.arg(format!("^{}", commit));
+    }
+    let out = command.output().expect("git failed to start");
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        if stderr.contains("empty bundle") {
+            return Ok(Created::Empty);
+        }
+        anyhow::bail!("{}", stderr.trim())
+    }
+    Ok(Created::Written)
+}
+
+/// Fetch the remote branches and tags of a bundle into a remote of a repository
+pub fn fetch(
+    git_dir: &Path,
+    bundle: &Path,
+    bundle_remote: &str,
+    remote_name: &str,
+) -> anyhow::Result<()> {
+    let out = Command::new("git")
+        .arg(format!("--git-dir={}", git_dir.to_str().unwrap()))
+        .arg("fetch")
+        .arg(bundle.to_str().expect("bundle path is utf8"))
+        .arg(format!(
+            "+refs/remotes/{}/*:refs/remotes/{}/*",
+            bundle_remote, remote_name
+        ))
+        .arg("+refs/tags/*:refs/tags/*")
+        .output()
+        .expect("git failed to start");
+    if !out.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim())
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use git2::{FetchPrune, Repository};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use super::commands::AppParams;
+use super::githelp;
+use super::util;
+
+/// File touched in a mirror every time it's used, to find the unused mirrors
+const LAST_USED_FILE: &str = "mugit-last-used";
+
+/// Directory of bare mirrors, used as a local object cache to clone from
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Cache { dir }
+    }
+
+    /// Path of the mirror of a repository url
+    pub fn mirror_path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.git", util::fnv1a64(url.as_bytes())))
+    }
+
+    /// Create or update the mirror of a repository url, returning its path
+    pub fn update(&self, app_params: &AppParams, url: &str) -> anyhow::Result<PathBuf> {
+        let mirror = self.mirror_path(url);
+        if app_params.git_exec {
+            let mut command = Command::new("git");
+            if mirror.exists() {
+                command
+                    .arg(format!("--git-dir={}", mirror.to_str().unwrap()))
+                    .arg("fetch")
+                    .arg("--prune")
+                    .arg("origin");
+            } else {
+                std::fs::create_dir_all(&self.dir)?;
+                command
+                    .arg("clone")
+                    .arg("--mirror")
+                    .arg(url)
+                    .arg(mirror.to_str().expect("cache dir is utf8"));
+            }
+            let out = command.output().expect("git failed to start");
+            if !out.status.success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim())
+            }
+        } else {
+            let repo = if mirror.exists() {
+                Repository::open_bare(&mirror)?
+            } else {
+                std::fs::create_dir_all(&self.dir)?;
+                let repo = Repository::init_bare(&mirror)?;
+                let _ = repo.remote_with_fetch("origin", url, "+refs/*:refs/*")?;
+                repo.config()?.set_bool("remote.origin.mirror", true)?;
+                repo
+            };
+            let mut fo = git2::FetchOptions::new();
+            fo.remote_callbacks(githelp::remote_callbacks());
+            fo.prune(FetchPrune::On);
+            let refspecs: &[&str] = &[];
+            repo.find_remote("origin")?
+                .fetch(refspecs, Some(&mut fo), None)?;
+        }
+        std::fs::write(mirror.join(LAST_USED_FILE), url)?;
+        Ok(mirror)
+    }
+
+    /// Remove the mirrors that have not been used for a duration
+    pub fn gc(&self, max_age: Duration, dry_run: bool) -> anyhow::Result<()> {
+        if !self.dir.is_dir() {
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("git") {
+                continue;
+            }
+            let last_used = last_used(&path);
+            let unused = match last_used {
+                None => true,
+                Some(t) => now.duration_since(t).unwrap_or_default() > max_age,
+            };
+            let url = mirror_url(&path).unwrap_or_else(|| "unknown url".to_string());
+            if !unused {
+                println!(
+                    "{} ({}) : {}",
+                    path.display(),
+                    url,
+                    ansi_term::Color::Green.paint("in use")
+                );
+            } else if dry_run {
+                println!(
+                    "{} ({}) : {}",
+                    path.display(),
+                    url,
+                    ansi_term::Color::Yellow.paint("would be removed")
+                );
+            } else {
+                std::fs::remove_dir_all(&path)?;
+                println!(
+                    "{} ({}) : {}",
+                    path.display(),
+                    url,
+                    ansi_term::Color::Red.paint("removed")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn last_used(mirror: &Path) -> Option<SystemTime> {
+    std::fs::metadata(mirror.join(LAST_USED_FILE))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+fn mirror_url(mirror: &Path) -> Option<String> {
+    let repo = Repository::open_bare(mirror).ok()?;
+    let remote = repo.find_remote("origin").ok()?;
+    remote.url().map(|s| s.to_string())
+}
@@ -0,0 +1,332 @@
+use git2::{Commit, ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+/// Dependency tables of a manifest, also found in the target specific tables
+pub const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// A crate of a repository, as read from its Cargo.toml
+pub struct CrateManifest {
+    /// path of the Cargo.toml in the repository
+    pub path: String,
+    pub name: String,
+    /// package version, none when the manifest has no version
+    pub version: Option<String>,
+}
+
+/// Read and parse a toml file of a commit
+fn read_toml(
+    repo: &Repository,
+    commit: &Commit,
+    path: &str,
+) -> anyhow::Result<Option<toml::Value>> {
+    let entry = match commit.tree()?.get_path(Path::new(path)) {
+        Ok(entry) => entry,
+        Err(_) => return Ok(None),
+    };
+    let blob = entry.to_object(repo)?.peel_to_blob()?;
+    let content =
+        std::str::from_utf8(blob.content()).map_err(|_| anyhow::anyhow!("{} is not utf8", path))?;
+    let value = toml::from_str(content).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+    Ok(Some(value))
+}
+
+/// Get the directories holding a Cargo.toml in the tree of a commit
+fn manifest_dirs(commit: &Commit) -> anyhow::Result<Vec<String>> {
+    let mut dirs = Vec::new();
+    commit.tree()?.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Blob) && entry.name() == Some("Cargo.toml") {
+            dirs.push(root.trim_end_matches('/').to_string());
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(dirs)
+}
+
+/// Get the `members` or `exclude` paths of the workspace of a root manifest
+fn workspace_paths(root: &toml::Value, key: &str) -> Vec<String> {
+    root.get("workspace")
+        .and_then(|w| w.get(key))
+        .and_then(|m| m.as_array())
+        .map(|m| {
+            m.iter()
+                .filter_map(|m| m.as_str())
+                .map(|m| m.trim_end_matches('/').to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Tell whether a member directory is excluded, as cargo does: when it is
+/// inside one of the excluded paths
+fn is_excluded(dir: &Path, exclude: &[String]) -> bool {
+    exclude.iter().any(|e| dir.starts_with(e))
+}
+
+/// Get the crates of a commit: the root package and the workspace members
+pub fn crates(repo: &Repository, commit: &Commit) -> anyhow::Result<Vec<CrateManifest>> {
+    let root = match read_toml(repo, commit, "Cargo.toml")? {
+        Some(root) => root,
+        None => return Ok(Vec::new()),
+    };
+    let workspace = root.get("workspace");
+    let workspace_version = workspace
+        .and_then(|w| w.get("package"))
+        .and_then(|p| p.get("version"))
+        .and_then(|v| v.as_str());
+    let members = workspace_paths(&root, "members")
+        .iter()
+        .filter_map(|m| glob::Pattern::new(m).ok())
+        .collect::<Vec<_>>();
+    let exclude = workspace_paths(&root, "exclude");
+
+    let mut paths = vec![String::new()];
+    if !members.is_empty() {
+        for dir in manifest_dirs(commit)? {
+            if !dir.is_empty()
+                && members.iter().any(|p| p.matches(&dir))
+                && !is_excluded(Path::new(&dir), &exclude)
+            {
+                paths.push(dir)
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for dir in paths {
+        let path = if dir.is_empty() {
+            "Cargo.toml".to_string()
+        } else {
+            format!("{}/Cargo.toml", dir)
+        };
+        let manifest = if dir.is_empty() {
+            Some(root.clone())
+        } else {
+            read_toml(repo, commit, &path)?
+        };
+        let package = match manifest.as_ref().and_then(|m| m.get("package")) {
+            Some(package) => package,
+            None => continue,
+        };
+        let name = match package.get("name").and_then(|n| n.as_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        // `version.workspace = true` inherits the version of the workspace
+        let version = match package.get("version") {
+            Some(toml::Value::String(v)) => Some(v.clone()),
+            Some(toml::Value::Table(t))
+                if t.get("workspace") == Some(&toml::Value::Boolean(true)) =>
+            {
+                workspace_version.map(|v| v.to_string())
+            }
+            _ => None,
+        };
+        out.push(CrateManifest {
+            path,
+            name,
+            version,
+        })
+    }
+    Ok(out)
+}
+
+/// Read and parse a Cargo.toml of a checkout
+pub fn read_manifest(path: &Path) -> Option<toml::Value> {
+    let content = std::fs::read_to_string(path).ok()?;
+    toml::from_str(&content).ok()
+}
+
+/// Get the Cargo.toml files of a checkout: the workspace members not excluded and the root one
+pub fn workspace_manifests(repo_dir: &Path) -> Vec<PathBuf> {
+    let root_path = repo_dir.join("Cargo.toml");
+    let root = match read_manifest(&root_path) {
+        Some(root) => root,
+        None => return Vec::new(),
+    };
+    let exclude = workspace_paths(&root, "exclude");
+    let mut out = Vec::new();
+    for member in workspace_paths(&root, "members") {
+        let pattern = repo_dir.join(member).join("Cargo.toml");
+        if let Ok(paths) = glob::glob(&pattern.to_string_lossy()) {
+            out.extend(paths.filter_map(|p| p.ok()).filter(|p| {
+                let dir = p
+                    .parent()
+                    .and_then(|d| d.strip_prefix(repo_dir).ok())
+                    .expect("member inside the repository");
+                *p != root_path && !is_excluded(dir, &exclude)
+            }))
+        }
+    }
+    out.push(root_path);
+    out
+}
+
+/// A dependency requirement changed by `bump_dependency`
+pub struct Bump {
+    /// table of the dependency, e.g. "dev-dependencies" or "workspace.dependencies"
+    pub table: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Keep the operator of a simple requirement, e.g. `=1.2` becomes `=2.0`
+fn new_requirement(old: &str, version: &str) -> String {
+    let op = old
+        .trim()
+        .chars()
+        .take_while(|c| matches!(c, '^' | '~' | '='))
+        .collect::<String>();
+    if old.contains(',') || old.contains('*') {
+        version.to_string()
+    } else {
+        format!("{}{}", op, version)
+    }
+}
+
+/// Replace a string value keeping its surrounding whitespace and comments
+fn set_requirement(value: &mut Value, requirement: &str) {
+    let decor = value.decor().clone();
+    *value = Value::from(requirement);
+    *value.decor_mut() = decor;
+}
+
+fn bump_in_table(
+    table: &mut dyn TableLike,
+    name: &str,
+    krate: &str,
+    version: &str,
+    bumps: &mut Vec<Bump>,
+) {
+    for (key, dep) in table.iter_mut() {
+        let renamed = dep
+            .get("package")
+            .and_then(|p| p.as_str())
+            .map(|p| p == krate);
+        if renamed != Some(true) && (renamed.is_some() || key.get() != krate) {
+            continue;
+        }
+        // `workspace = true` dependencies are bumped in the workspace table,
+        // and path or git only dependencies have no requirement
+        let value = match dep {
+            Item::Value(value @ Value::String(_)) => value,
+            _ => match dep.as_table_like_mut().and_then(|t| t.get_mut("version")) {
+                Some(Item::Value(value @ Value::String(_))) => value,
+                _ => continue,
+            },
+        };
+        let old = value.as_str().expect("string requirement").to_string();
+        let new = new_requirement(&old, version);
+        if old != new {
+            set_requirement(value, &new);
+            bumps.push(Bump {
+                table: name.to_string(),
+                old,
+                new,
+            })
+        }
+    }
+}
+
+/// Change the requirement of a crate in all the dependency tables of a manifest
+pub fn bump_dependency(doc: &mut DocumentMut, krate: &str, version: &str) -> Vec<Bump> {
+    let mut bumps = Vec::new();
+    for key in DEPENDENCY_TABLES.iter() {
+        if let Some(table) = doc.get_mut(key).and_then(|t| t.as_table_like_mut()) {
+            bump_in_table(table, key, krate, version, &mut bumps)
+        }
+    }
+    if let Some(table) = doc
+        .get_mut("workspace")
+        .and_then(|w| w.get_mut("dependencies"))
+        .and_then(|t| t.as_table_like_mut())
+    {
+        bump_in_table(table, "workspace.dependencies", krate, version, &mut bumps)
+    }
+    if let Some(targets) = doc.get_mut("target").and_then(|t| t.as_table_like_mut()) {
+        for (target, deps) in targets.iter_mut() {
+            for key in DEPENDENCY_TABLES.iter() {
+                if let Some(table) = deps.get_mut(key).and_then(|t| t.as_table_like_mut()) {
+                    let name = format!("target.{}.{}", target.get(), key);
+                    bump_in_table(table, &name, krate, version, &mut bumps)
+                }
+            }
+        }
+    }
+    bumps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirement_operators() {
+        assert_eq!(new_requirement("1.2", "2.0"), "2.0");
+        assert_eq!(new_requirement("=1.2.3", "2.0.1"), "=2.0.1");
+        assert_eq!(new_requirement(" ~1.2", "1.4"), "~1.4");
+        assert_eq!(new_requirement(">=1.2, <2", "2.1"), "2.1");
+    }
+
+    #[test]
+    fn bump_with_workspace_inheritance() {
+        let mut doc = r#"[workspace]
+members = ["crates/*"]
+
+[workspace.dependencies]
+serde = { version = "1.0", features = ["derive"] } # shared
+log = "0.4"
+
+[dependencies]
+serde = { workspace = true }
+ser = { package = "serde", version = "=1.0.100" }
+
+[target.'cfg(unix)'.dev-dependencies]
+serde = "~1.0"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+
+        let bumps = bump_dependency(&mut doc, "serde", "1.0.200");
+        let bumps = bumps
+            .iter()
+            .map(|b| (b.table.as_str(), b.old.as_str(), b.new.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bumps,
+            [
+                ("dependencies", "=1.0.100", "=1.0.200"),
+                ("workspace.dependencies", "1.0", "1.0.200"),
+                ("target.cfg(unix).dev-dependencies", "~1.0", "~1.0.200"),
+            ]
+        );
+        let out = doc.to_string();
+        assert!(out.contains(r#"serde = { version = "1.0.200", features = ["derive"] } # shared"#));
+        assert!(out.contains("serde = { workspace = true }"));
+        assert!(out.contains(r#"log = "0.4""#));
+
+        assert!(bump_dependency(&mut doc, "serde", "1.0.200").is_empty());
+    }
+
+    #[test]
+    fn manifests_without_excluded_members() {
+        let dir = std::env::temp_dir().join(format!("mugit-workspace-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for member in ["crates/a", "crates/b", "crates/old/c"] {
+            std::fs::create_dir_all(dir.join(member)).unwrap();
+            std::fs::write(dir.join(member).join("Cargo.toml"), "[package]\n").unwrap();
+        }
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\", \"crates/old/*\"]\nexclude = [\"crates/old\", \"crates/b/\"]\n",
+        )
+        .unwrap();
+
+        let manifests = workspace_manifests(&dir);
+        assert_eq!(
+            manifests,
+            [dir.join("crates/a/Cargo.toml"), dir.join("Cargo.toml")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,25 +1,37 @@
-use git2::Repository;
+use git2::{BranchType, Repository};
 use semver::Version;
 use std::process::Command;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 
+use super::archive::Archive;
+use super::bundle::{self, BundleEntry, BundleIndex};
+use super::cache::Cache;
+use super::cargo;
 use super::githelp;
-use super::manifest::{Manifest, Manifests, Project};
+use super::graph::DepGraph;
+use super::lock::{Lock, LockedRepo, LOCK_FILE_NAME};
+use super::manifest::{Manifest, Manifests, Project, Repo};
 use super::project::{on_project, on_project_repos};
+use super::topic::{Topic, Topics};
 use super::util;
 use super::ver::*;
 
 #[derive(Clone, Debug)]
 pub struct AppParams {
     pub git_exec: bool,
+    pub offline: bool,
     pub sys_manifests: Arc<Option<Manifests>>,
     pub manifest_selector: Option<String>,
     pub manifest_file: Option<PathBuf>,
     pub manifest_dest: Option<PathBuf>,
+    pub remote: Option<String>,
+    /// iterate over the repositories after their dependencies
+    pub topo: bool,
 }
 
 pub enum Selector {
@@ -59,21 +71,15 @@ impl AppParams {
                     Some(manifests) => manifests.get(&selector).expect("existing manifest").clone(),
                 },
             },
-            Some(manifest_file) => {
-                let manifest = Manifest::from_file(manifest_file)
-                    .unwrap()
-                    .expect("valid manifest file");
-                manifest
-            }
+            Some(manifest_file) => Manifest::from_file(manifest_file)
+                .unwrap()
+                .expect("valid manifest file"),
         }
     }
 
     pub fn get_destpath(&self) -> PathBuf {
         match &self.manifest_dest {
-            None => {
-                let current_dir = std::env::current_dir().unwrap();
-                current_dir
-            }
+            None => std::env::current_dir().unwrap(),
             Some(out_dir) => {
                 if !out_dir.exists() {
                     panic!("out directory doesn't exist {:?}", out_dir)
@@ -95,12 +101,17 @@ impl AppParams {
         (manifest, project)
     }
 
+    /// Get the remote to resolve and push to for a repository, either the one
+    /// specified on the command line or the repository main remote
+    pub fn remote_name<'a>(&'a self, project: &'a Project, repo: &'a Repo) -> &'a str {
+        self.remote
+            .as_deref()
+            .unwrap_or_else(|| repo.remote_name(project))
+    }
+
     pub fn get_destpath_create(&self) -> PathBuf {
         match &self.manifest_dest {
-            None => {
-                let current_dir = std::env::current_dir().unwrap();
-                current_dir
-            }
+            None => std::env::current_dir().unwrap(),
             Some(dest) => {
                 if dest.exists() {
                     if !dest.is_dir() {
@@ -120,6 +131,28 @@ fn repo_report_error(name: &str, s: &str) {
     println!("{:40} : {}", name, ansi_term::Color::Purple.paint(s))
 }
 
+/// In offline mode, warn that the remote-tracking branches might be stale
+fn report_offline(app_params: &AppParams, name: &str, repo: &Repository) {
+    if !app_params.offline {
+        return;
+    }
+    println!(
+        "{:40} : {}",
+        name,
+        ansi_term::Color::Yellow.paint(format!(
+            "offline, remote refs may be stale ({})",
+            last_fetch_description(repo)
+        ))
+    )
+}
+
+pub(crate) fn last_fetch_description(repo: &Repository) -> String {
+    match githelp::last_fetch_time(repo) {
+        None => "never fetched".to_string(),
+        Some(t) => format!("last fetched {}", util::format_age(t)),
+    }
+}
+
 fn filter_matches<'a, I>(specified: Spec, it: I) -> impl Iterator<Item = &'a Version>
 where
     I: Iterator<Item = &'a Version>,
@@ -127,99 +160,331 @@ where
     it.filter(move |v| specified.fullfill(v.major, v.minor, v.patch))
 }
 
-pub fn git_clone(app_params: &AppParams, url: &str, dest_repo: &Path) -> Repository {
-    if app_params.git_exec {
-        let _out = Command::new("git")
-            .arg("clone")
+/// Options to create shallow or partial clones
+#[derive(Clone, Debug, Default)]
+pub struct CloneOptions {
+    pub depth: Option<u32>,
+    pub single_branch: bool,
+    pub filter: Option<String>,
+}
+
+impl CloneOptions {
+    /// Get the options for a repository, the command line options taking precedence
+    pub fn for_repo(&self, repo: &Repo) -> CloneOptions {
+        CloneOptions {
+            depth: self.depth.or(repo.depth),
+            single_branch: self.single_branch || repo.single_branch,
+            filter: self.filter.clone().or_else(|| repo.filter.clone()),
+        }
+    }
+}
+
+/// How much history to fetch
+#[derive(Clone, Copy)]
+pub enum FetchDepth {
+    Default,
+    Depth(u32),
+    Unshallow,
+}
+
+pub fn git_clone(
+    app_params: &AppParams,
+    url: &str,
+    dest_repo: &Path,
+    remote_name: &str,
+    options: &CloneOptions,
+) -> anyhow::Result<Repository> {
+    // libgit2 doesn't support partial clones
+    if app_params.git_exec || options.filter.is_some() {
+        let mut command = Command::new("git");
+        command.arg("clone").arg("--origin").arg(remote_name);
+        if let Some(depth) = options.depth {
+            command.arg(format!("--depth={}", depth));
+        }
+        if options.single_branch {
+            command.arg("--single-branch");
+        } else if options.depth.is_some() {
+            // --depth implies --single-branch
+            command.arg("--no-single-branch");
+        }
+        if let Some(filter) = &options.filter {
+            command.arg(format!("--filter={}", filter));
+        }
+        let out = command
             .arg(url)
             .arg(dest_repo.to_str().expect("dest repo is utf8"))
-            .output()
-            .expect("git failed to start");
-        let repo = Repository::open(&dest_repo).expect("git repository");
-        repo
+            .output()?;
+        if !out.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim())
+        }
+        Ok(Repository::open(dest_repo)?)
     } else {
         let callbacks = githelp::remote_callbacks();
         let mut fo = git2::FetchOptions::new();
         fo.remote_callbacks(callbacks);
+        if let Some(depth) = options.depth {
+            fo.depth(depth as i32);
+        }
+
+        let default_branch = if options.single_branch {
+            Some(githelp::remote_default_branch(url)?)
+        } else {
+            None
+        };
 
         let mut builder = git2::build::RepoBuilder::new();
         builder.fetch_options(fo);
+        if let Some(branch) = &default_branch {
+            builder.branch(branch);
+        }
+        builder.remote_create(|repo, _name, url| match &default_branch {
+            None => repo.remote(remote_name, url),
+            Some(branch) => {
+                let refspec = format!(
+                    "+refs/heads/{}:refs/remotes/{}/{}",
+                    branch, remote_name, branch
+                );
+                repo.remote_with_fetch(remote_name, url, &refspec)
+            }
+        });
 
-        builder.clone(url, dest_repo).expect("cloning error")
+        Ok(builder.clone(url, dest_repo)?)
     }
 }
 
-fn git_fetch_all(app_params: &AppParams, repo: &Repository) {
-    let source = "origin";
-    if app_params.git_exec {
+pub(crate) fn git_fetch(
+    app_params: &AppParams,
+    repo: &Repository,
+    source: &str,
+    depth: FetchDepth,
+) -> anyhow::Result<()> {
+    // libgit2 doesn't support partial clones
+    if app_params.git_exec || githelp::is_partial_clone(repo) {
         let workdir = repo.workdir().expect("workdir exists");
         let path = repo.path();
-        let _out = Command::new("git")
-            .arg(&format!("--git-dir={}", path.to_str().unwrap()))
-            .arg(&format!("--work-tree={}", workdir.to_str().unwrap()))
-            .arg("fetch")
-            .arg(source)
-            .output()
-            .expect("git failed to start");
+        let mut command = Command::new("git");
+        command
+            .arg(format!("--git-dir={}", path.to_str().unwrap()))
+            .arg(format!("--work-tree={}", workdir.to_str().unwrap()))
+            .arg("fetch");
+        match depth {
+            FetchDepth::Default => {}
+            FetchDepth::Depth(depth) => {
+                command.arg(format!("--depth={}", depth));
+            }
+            FetchDepth::Unshallow => {
+                command.arg("--unshallow");
+            }
+        }
+        let out = command.arg(source).output().expect("git failed to start");
+        if !out.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim())
+        }
     } else {
         let callbacks2 = githelp::remote_callbacks();
 
         let mut mfo = git2::FetchOptions::new();
         mfo.remote_callbacks(callbacks2);
+        match depth {
+            FetchDepth::Default => {}
+            FetchDepth::Depth(depth) => {
+                mfo.depth(depth as i32);
+            }
+            FetchDepth::Unshallow => {
+                // GIT_FETCH_DEPTH_UNSHALLOW
+                mfo.depth(i32::MAX);
+            }
+        }
 
         let mfo = Some(&mut mfo);
         let refspecs: &[&str] = &[];
-        repo.find_remote(source)
-            .expect("cannot find origin")
-            .fetch(refspecs, mfo, None)
-            .expect("fetch error")
+        repo.find_remote(source)?.fetch(refspecs, mfo, None)?
+    }
+    Ok(())
+}
+
+/// Fetch all the given remotes, reporting the failing ones without stopping
+fn git_fetch_all(
+    app_params: &AppParams,
+    repo: &Repository,
+    name: &str,
+    sources: &[&str],
+    depth: FetchDepth,
+) {
+    for source in sources {
+        if let Err(e) = git_fetch(app_params, repo, source, depth) {
+            repo_report_error(name, &format!("fetching {} failed: {}", source, e))
+        }
+    }
+}
+
+/// Create the missing remotes and update the url of the existing ones
+fn git_setup_remotes(repo: &Repository, remotes: &[(String, String)]) -> anyhow::Result<()> {
+    for (remote_name, url) in remotes {
+        match repo.find_remote(remote_name) {
+            Ok(remote) => {
+                if remote.url() != Some(url.as_str()) {
+                    println!(
+                        "  {} {} to {:?}",
+                        ansi_term::Color::Blue.paint("updating remote"),
+                        remote_name,
+                        url
+                    );
+                    repo.remote_set_url(remote_name, url)?
+                }
+            }
+            Err(_) => {
+                println!(
+                    "  {} {} at {:?}",
+                    ansi_term::Color::Blue.paint("adding remote"),
+                    remote_name,
+                    url
+                );
+                let _ = repo.remote(remote_name, url)?;
+            }
+        }
     }
+    Ok(())
 }
 
 pub enum PushSpecifier<'a> {
     Tag(&'a str),
     Branch(&'a str),
+    /// refspecs as `[+]<src>:<dst>`, where src and dst can have a `*` wildcard
+    Refspecs(&'a [String]),
 }
 
+/// Outcome of pushing one reference
+pub enum PushStatus {
+    Updated,
+    Unchanged,
+    Rejected(String),
+}
+
+/// Push to a remote, given by name or url, returning the status of each destination reference
 fn git_push_to<'a>(
     app_params: &AppParams,
-    project: &Project,
+    remote: &str,
     repo: &Repository,
     spec: PushSpecifier<'a>,
-) {
-    if app_params.git_exec {
-        let workdir = repo.workdir().expect("workdir exists");
-        let path = repo.path();
-        let spec_str = match spec {
-            PushSpecifier::Tag(s) => s,
-            PushSpecifier::Branch(s) => s,
-        };
+) -> anyhow::Result<Vec<(String, PushStatus)>> {
+    let refspecs = match spec {
+        PushSpecifier::Tag(tag) => vec![format!("refs/tags/{}:refs/tags/{}", tag, tag)],
+        PushSpecifier::Branch(branch) => {
+            vec![format!("refs/heads/{}:refs/heads/{}", branch, branch)]
+        }
+        PushSpecifier::Refspecs(refspecs) => refspecs.to_vec(),
+    };
+    let push_refs = githelp::expand_push_refspecs(repo, &refspecs)?;
+    if push_refs.is_empty() {
+        return Ok(Vec::new());
+    }
 
-        let _out = Command::new("git")
-            .arg(&format!("--git-dir={}", path.to_str().unwrap()))
-            .arg(&format!("--work-tree={}", workdir.to_str().unwrap()))
+    if app_params.git_exec {
+        let mut command = Command::new("git");
+        command
+            .arg(format!("--git-dir={}", repo.path().to_str().unwrap()))
             .arg("push")
-            .arg(&project.remote_name)
-            .arg(spec_str)
-            .output()
-            .expect("git failed to start");
+            .arg("--porcelain")
+            .arg(remote);
+        for r in push_refs.iter() {
+            command.arg(format!(
+                "{}{}:{}",
+                if r.force { "+" } else { "" },
+                r.src,
+                r.dst
+            ));
+        }
+        let out = command.output().expect("git failed to start");
+
+        // porcelain lines are "<flag>\t<src>:<dst>\t<summary>"
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let mut statuses = Vec::new();
+        for line in stdout.lines() {
+            let mut fields = line.split('\t');
+            let (flag, refs, summary) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(flag), Some(refs), Some(summary)) => (flag, refs, summary),
+                _ => continue,
+            };
+            let dst = refs.split_once(':').map_or(refs, |(_, dst)| dst);
+            let status = match flag {
+                "=" => PushStatus::Unchanged,
+                "!" => PushStatus::Rejected(summary.to_string()),
+                _ => PushStatus::Updated,
+            };
+            statuses.push((dst.to_string(), status))
+        }
+        if statuses.is_empty() && !out.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim())
+        }
+        Ok(statuses)
     } else {
         let mut remote = repo
-            .find_remote(&project.remote_name)
-            .expect("remote exists");
-        let callbacks = githelp::remote_callbacks();
-
-        let mut push_options = git2::PushOptions::new();
-        push_options.remote_callbacks(callbacks);
+            .find_remote(remote)
+            .or_else(|_| repo.remote_anonymous(remote))?;
+
+        // libgit2 fails the whole push on the first non fast-forward reference,
+        // so find out locally what is unchanged or not a fast-forward from the
+        // references advertised by the remote
+        let remote_heads = githelp::remote_heads(&mut remote)?;
+        let mut statuses = Vec::new();
+        let mut to_push = Vec::new();
+        for r in push_refs.iter() {
+            let local = repo.refname_to_id(&r.src)?;
+            let status = match remote_heads.get(&r.dst) {
+                Some(remote_oid) if *remote_oid == local => Some(PushStatus::Unchanged),
+                Some(remote_oid)
+                    if !r.force
+                        && !repo
+                            .graph_descendant_of(local, *remote_oid)
+                            .unwrap_or(false) =>
+                {
+                    Some(PushStatus::Rejected("non-fast-forward".to_string()))
+                }
+                _ => None,
+            };
+            match status {
+                Some(status) => statuses.push((r.dst.clone(), status)),
+                None => to_push.push(r),
+            }
+        }
 
-        let ref_to_push = match spec {
-            PushSpecifier::Tag(tag) => format!("refs/tags/{}", tag),
-            PushSpecifier::Branch(branch) => format!("refs/heads/{}", branch),
-        };
+        let remote_statuses = RefCell::new(HashMap::new());
+        if !to_push.is_empty() {
+            let mut callbacks = githelp::remote_callbacks();
+            callbacks.push_update_reference(|refname, status| {
+                remote_statuses
+                    .borrow_mut()
+                    .insert(refname.to_string(), status.map(|s| s.to_string()));
+                Ok(())
+            });
+            let mut push_options = git2::PushOptions::new();
+            push_options.remote_callbacks(callbacks);
+
+            let refspecs = to_push
+                .iter()
+                .map(|r| format!("{}{}:{}", if r.force { "+" } else { "" }, r.src, r.dst))
+                .collect::<Vec<_>>();
+            remote.push(&refspecs, Some(&mut push_options))?;
+        }
+        let remote_statuses = remote_statuses.into_inner();
+        for r in to_push {
+            let status = match remote_statuses.get(&r.dst) {
+                Some(Some(message)) => PushStatus::Rejected(message.clone()),
+                _ => PushStatus::Updated,
+            };
+            statuses.push((r.dst.clone(), status))
+        }
+        Ok(statuses)
+    }
+}
 
-        remote
-            .push(&[ref_to_push], Some(&mut push_options))
-            .expect("push error");
+/// Report the references a push didn't update
+fn report_push_rejected(name: &str, statuses: &[(String, PushStatus)]) {
+    for (reference, status) in statuses {
+        if let PushStatus::Rejected(reason) = status {
+            repo_report_error(name, &format!("push of {} rejected: {}", reference, reason))
+        }
     }
 }
 
@@ -282,19 +547,8 @@ pub fn manifest_debug(manifest_file: &str) {
 
 pub fn manifest_has_branch(app_params: &AppParams, branch: &str) -> anyhow::Result<()> {
     on_project(app_params, |out_dir, _, project| {
-        for repo in project.repos.iter() {
-            let _p = Path::new(repo);
-            let dest_repo = match _p.file_name() {
-                None => {
-                    println!("ignoring {}", repo);
-                    continue;
-                }
-                Some(s) => {
-                    let mut x = out_dir.to_path_buf();
-                    x.push(s);
-                    x
-                }
-            };
+        for (repo_entry, dest) in project.destinations()? {
+            let dest_repo = out_dir.join(&dest);
 
             if !dest_repo.exists() {
                 panic!(
@@ -303,13 +557,14 @@ pub fn manifest_has_branch(app_params: &AppParams, branch: &str) -> anyhow::Resu
                 )
             }
             let repo = Repository::open(&dest_repo).expect("git repository");
-            let has_branch = githelp::has_remote_branch(&repo, &project.remote_name, branch);
+            let has_branch = githelp::has_remote_branch(
+                &repo,
+                app_params.remote_name(project, repo_entry),
+                branch,
+            );
 
-            let name = dest_repo
-                .file_name()
-                .expect("file name")
-                .to_str()
-                .expect("git with valid UTF8");
+            let name = dest.to_str().expect("git with valid UTF8");
+            report_offline(app_params, name, &repo);
             if has_branch {
                 println!(
                     "{:40} : {} branch {}   ✅",
@@ -331,8 +586,9 @@ pub fn manifest_has_branch(app_params: &AppParams, branch: &str) -> anyhow::Resu
 }
 
 pub fn manifest_has_tag(app_params: &AppParams, tag: &str) -> anyhow::Result<()> {
-    on_project_repos(app_params, |_, dest_repo, name| {
+    on_project_repos(app_params, |_, _, dest_repo, name| {
         let repo = Repository::open(&dest_repo).expect("git repository");
+        report_offline(app_params, name, &repo);
         let has_tag = githelp::has_remote_tag(&repo, tag);
 
         if has_tag {
@@ -357,19 +613,17 @@ pub fn manifest_has_tag(app_params: &AppParams, tag: &str) -> anyhow::Result<()>
 
 fn find_branch_commit<'a>(
     repo: &'a Repository,
-    project: &'a Project,
+    remote_name: &str,
     branch: &str,
     or_branch: Option<&str>,
 ) -> Result<git2::Commit<'a>, String> {
-    match githelp::remote_resolve_branch(repo, &project.remote_name, branch) {
+    match githelp::remote_resolve_branch(repo, remote_name, branch) {
         Ok(commit) => Ok(commit),
         Err(_e1) => match or_branch {
-            Some(or_branch) => {
-                match githelp::remote_resolve_branch(repo, &project.remote_name, or_branch) {
-                    Ok(commit2) => Ok(commit2),
-                    Err(_e2) => Err(format!("branch {} or {} not available", branch, or_branch)),
-                }
-            }
+            Some(or_branch) => match githelp::remote_resolve_branch(repo, remote_name, or_branch) {
+                Ok(commit2) => Ok(commit2),
+                Err(_e2) => Err(format!("branch {} or {} not available", branch, or_branch)),
+            },
             None => Err(format!("branch {} not available", branch)),
         },
     }
@@ -382,14 +636,14 @@ pub fn manifest_set_branch(
     skip_push: bool,
     continue_if_exists: bool,
 ) -> anyhow::Result<()> {
-    on_project_repos(app_params, |project, dest_repo, name| {
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
         let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
 
-        let has_branch =
-            match githelp::remote_resolve_branch(&repo, &project.remote_name, name_branch) {
-                Ok(_commit) => true,
-                Err(_e1) => false,
-            };
+        let has_branch = match githelp::remote_resolve_branch(&repo, remote_name, name_branch) {
+            Ok(_commit) => true,
+            Err(_e1) => false,
+        };
         if has_branch {
             repo_report_error(name, &format!("branch {} already exist", name_branch));
             if !continue_if_exists {
@@ -398,12 +652,12 @@ pub fn manifest_set_branch(
             return Ok(());
         }
 
-        let target = match githelp::remote_resolve_branch(&repo, &project.remote_name, commit) {
+        let target = match githelp::remote_resolve_branch(&repo, remote_name, commit) {
             Ok(commit) => commit,
             Err(_e1) => {
                 println!(
                     "fail to setup '{}' branch for {} : resolution of {} failed",
-                    name_branch, project.remote_name, commit
+                    name_branch, remote_name, commit
                 );
                 return Ok(());
             }
@@ -414,7 +668,7 @@ pub fn manifest_set_branch(
             Err(e) => {
                 println!(
                     "fail to setup '{}' branch for {}: creating branch return error: {}",
-                    name_branch, project.remote_name, e
+                    name_branch, remote_name, e
                 );
                 return Ok(());
             }
@@ -428,19 +682,21 @@ pub fn manifest_set_branch(
             commit
         );
 
-        if skip_push {
+        if skip_push || app_params.offline {
             println!(
-                "git --git-dir={}/.git push origin {}",
+                "git --git-dir={}/.git push {} {}",
                 dest_repo.into_os_string().into_string().unwrap(),
+                remote_name,
                 name_branch
             );
         } else {
-            git_push_to(
-                &app_params,
-                &project,
+            let statuses = git_push_to(
+                app_params,
+                remote_name,
                 &repo,
                 PushSpecifier::Branch(name_branch),
-            );
+            )?;
+            report_push_rejected(name, &statuses);
         }
 
         Ok(())
@@ -449,6 +705,85 @@ pub fn manifest_set_branch(
     Ok(())
 }
 
+/// Compare the package version of every crate at the branch commit with a release tag,
+/// returning the number of mismatches
+fn check_cargo_versions(
+    app_params: &AppParams,
+    branch: &str,
+    or_branch: Option<&str>,
+    tag: &str,
+) -> anyhow::Result<usize> {
+    let tag_version = Version::parse(tag.strip_prefix('v').unwrap_or(tag))
+        .map_err(|e| anyhow::anyhow!("tag {} is not a version: {}", tag, e))?;
+
+    let mismatches = on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        let commit = match find_branch_commit(&repo, remote_name, branch, or_branch) {
+            Ok(commit) => commit,
+            Err(e) => {
+                repo_report_error(name, &e);
+                return Ok(1);
+            }
+        };
+
+        let crates = cargo::crates(&repo, &commit)?;
+        if crates.is_empty() {
+            println!(
+                "{:40} : {}",
+                name,
+                ansi_term::Color::Yellow.paint("no Cargo.toml")
+            );
+        }
+        let mut mismatches = 0;
+        for krate in crates {
+            let version = match &krate.version {
+                Some(version) => version,
+                None => continue,
+            };
+            let target = format!("{}/{}", name, krate.path);
+            match Version::parse(version) {
+                Ok(v) if v == tag_version => println!(
+                    "{:40} : {} {}",
+                    target,
+                    krate.name,
+                    ansi_term::Color::Green.paint(version)
+                ),
+                Ok(_) => {
+                    mismatches += 1;
+                    println!(
+                        "{:40} : {} {} does not match tag {}",
+                        target,
+                        krate.name,
+                        ansi_term::Color::Red.paint(version),
+                        tag
+                    )
+                }
+                Err(e) => {
+                    mismatches += 1;
+                    repo_report_error(&target, &format!("invalid version {}: {}", version, e))
+                }
+            }
+        }
+        Ok(mismatches)
+    })?;
+    Ok(mismatches.into_iter().sum())
+}
+
+/// Check that the crates of every repository have the version of a release tag
+pub fn manifest_check_versions(
+    app_params: &AppParams,
+    branch: &str,
+    or_branch: Option<&str>,
+    tag: &str,
+) -> anyhow::Result<()> {
+    let mismatches = check_cargo_versions(app_params, branch, or_branch, tag)?;
+    if mismatches > 0 {
+        anyhow::bail!("{} crate versions do not match {}", mismatches, tag)
+    }
+    Ok(())
+}
+
 pub fn manifest_set_tag(
     app_params: &AppParams,
     branch: &str,
@@ -456,9 +791,21 @@ pub fn manifest_set_tag(
     skip_push: bool,
     continue_if_exists: bool,
     or_branch: Option<&str>,
+    check_cargo_version: bool,
 ) -> anyhow::Result<()> {
+    if check_cargo_version {
+        let mismatches = check_cargo_versions(app_params, branch, or_branch, tag)?;
+        if mismatches > 0 {
+            anyhow::bail!(
+                "{} crate versions do not match {}, not tagging",
+                mismatches,
+                tag
+            )
+        }
+    }
+
     // first chunk test that all repos are ok
-    let _r = on_project_repos(app_params, |project, dest_repo, name| {
+    let _r = on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
         let repo = Repository::open(&dest_repo).expect("git repository");
         let has_tag = githelp::has_remote_tag(&repo, tag);
 
@@ -470,38 +817,45 @@ pub fn manifest_set_tag(
             anyhow::bail!("tag exists")
         }
 
-        let _commit =
-            find_branch_commit(&repo, project, branch, or_branch).expect("resolve branch");
+        let _commit = find_branch_commit(
+            &repo,
+            app_params.remote_name(project, repo_entry),
+            branch,
+            or_branch,
+        )
+        .expect("resolve branch");
 
         Ok(())
     })?;
 
     // then we re-loop over all repos, and tag/push then.
-    on_project_repos(app_params, |project, dest_repo, name| {
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
         let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
 
-        let commit = find_branch_commit(&repo, project, branch, or_branch).expect("resolve branch");
+        let commit =
+            find_branch_commit(&repo, remote_name, branch, or_branch).expect("resolve branch");
 
         println!("{}: tagging repo with commit {}", name, commit.id());
 
         let dry_run = false;
         let target = commit.into_object();
-        if dry_run {
-            ()
-        } else {
+        if !dry_run {
             repo.tag_lightweight(tag, &target, false)
                 .expect("tag failed");
         }
 
-        if skip_push {
+        if skip_push || app_params.offline {
             println!(
-                "git --git-dir={}/.git push origin {}",
+                "git --git-dir={}/.git push {} {}",
                 dest_repo.into_os_string().into_string().unwrap(),
+                remote_name,
                 tag
             );
             Ok(())
         } else {
-            git_push_to(&app_params, &project, &repo, PushSpecifier::Tag(tag));
+            let statuses = git_push_to(app_params, remote_name, &repo, PushSpecifier::Tag(tag))?;
+            report_push_rejected(name, &statuses);
             Ok(())
         }
     })?;
@@ -509,16 +863,97 @@ pub fn manifest_set_tag(
     Ok(())
 }
 
+pub fn manifest_checkout(
+    app_params: &AppParams,
+    branch: &str,
+    or_branch: Option<&str>,
+    create: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    let summary = on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
+
+        if !force && githelp::is_dirty(&repo) {
+            repo_report_error(name, "has local changes, not checking out");
+            return Ok((name.to_string(), false, githelp::current_branch(&repo)));
+        }
+
+        // use the local branch if it exists, otherwise resolve the remote branch
+        // and create a local branch tracking it
+        let target_branch = if repo.find_branch(branch, BranchType::Local).is_ok() {
+            branch
+        } else {
+            let commit = match find_branch_commit(&repo, remote_name, branch, or_branch) {
+                Ok(commit) => commit,
+                Err(e) => {
+                    repo_report_error(name, &e);
+                    return Ok((name.to_string(), false, githelp::current_branch(&repo)));
+                }
+            };
+            let resolved = if githelp::has_remote_branch(&repo, remote_name, branch) {
+                branch
+            } else {
+                or_branch.expect("or-branch resolved")
+            };
+            let local = if create { branch } else { resolved };
+
+            if repo.find_branch(local, BranchType::Local).is_err() {
+                let mut local_branch = repo.branch(local, &commit, false)?;
+                if local == resolved {
+                    local_branch.set_upstream(Some(&format!("{}/{}", remote_name, resolved)))?;
+                }
+                println!(
+                    "{}: creating branch '{}' with commit {} (branch={})",
+                    name,
+                    local,
+                    commit.id(),
+                    resolved
+                );
+            }
+            local
+        };
+
+        if githelp::current_branch(&repo).as_deref() != Some(target_branch) {
+            if let Err(e) = githelp::checkout_branch(&repo, target_branch, force) {
+                repo_report_error(
+                    name,
+                    &format!("checkout of {} failed: {}", target_branch, e),
+                );
+                return Ok((name.to_string(), false, githelp::current_branch(&repo)));
+            }
+        }
+        Ok((name.to_string(), true, githelp::current_branch(&repo)))
+    })?;
+
+    println!();
+    for (name, switched, current) in summary {
+        let current = current.unwrap_or_else(|| "detached HEAD".to_string());
+        let color = if !switched {
+            ansi_term::Color::Red
+        } else if current == branch {
+            ansi_term::Color::Green
+        } else {
+            ansi_term::Color::Yellow
+        };
+        println!("{:40} : {}", name, color.paint(current))
+    }
+    Ok(())
+}
+
 pub fn manifest_has_change(
     app_params: &AppParams,
     tag: &str,
     branch: &str,
     continue_on_fail: bool,
 ) -> anyhow::Result<()> {
-    on_project_repos(app_params, |project, dest_repo, name| {
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
         let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
         let has_tag = githelp::has_remote_tag(&repo, tag);
-        let has_branch = githelp::has_remote_branch(&repo, &project.remote_name, branch);
+        let has_branch = githelp::has_remote_branch(&repo, remote_name, branch);
 
         if !has_tag {
             if continue_on_fail {
@@ -538,7 +973,7 @@ pub fn manifest_has_change(
         let all_tags = githelp::all_tags(&repo);
         let tag_oid = all_tags.get(tag).expect("existing tag");
 
-        let to_find = format!("{}/{}", project.remote_name, branch);
+        let to_find = format!("{}/{}", remote_name, branch);
         let branch = githelp::remote_branches_get_name(&repo, &to_find).expect("branch found");
         let branch_ref = branch.into_reference();
         let branch_commit = branch_ref.peel_to_commit().expect("branch oid found");
@@ -563,6 +998,15 @@ pub fn manifest_has_change(
     Ok(())
 }
 
+/// Resolve a revision as a tag, or else as a branch of the remote
+pub(crate) fn resolve_rev(repo: &Repository, remote_name: &str, rev: &str) -> Option<git2::Oid> {
+    githelp::all_tags(repo).get(rev).cloned().or_else(|| {
+        githelp::remote_resolve_branch(repo, remote_name, rev)
+            .map(|c| c.id())
+            .ok()
+    })
+}
+
 pub fn manifest_changelog(
     app_params: &AppParams,
     rev1: &str,
@@ -570,24 +1014,13 @@ pub fn manifest_changelog(
     continue_on_fail: bool,
     show_no_diff: bool,
 ) -> anyhow::Result<()> {
-    on_project_repos(app_params, |project, dest_repo, name| {
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
         let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
 
-        let all_tags = githelp::all_tags(&repo);
-
-        let rev1_resolve = all_tags.get(rev1).cloned();
-        let rev2_resolve = all_tags.get(rev2).cloned();
-
-        let rev1_resolve = rev1_resolve.or_else(|| {
-            githelp::remote_resolve_branch(&repo, &project.remote_name, rev1)
-                .map(|c| c.id())
-                .ok()
-        });
-        let rev2_resolve = rev2_resolve.or_else(|| {
-            githelp::remote_resolve_branch(&repo, &project.remote_name, rev2)
-                .map(|c| c.id())
-                .ok()
-        });
+        let rev1_resolve = resolve_rev(&repo, remote_name, rev1);
+        let rev2_resolve = resolve_rev(&repo, remote_name, rev2);
 
         if rev1_resolve.is_none() {
             if continue_on_fail {
@@ -623,62 +1056,1533 @@ pub fn manifest_changelog(
         if output.is_empty() {
             if show_no_diff {
                 println!("## no differences for {}", name);
-                println!("");
+                println!();
             }
         } else {
             println!("## differences for {}", name);
             println!("{}", output);
-            println!("");
+            println!();
         }
         Ok(())
     })?;
     Ok(())
 }
 
-pub fn manifest_sync(app_params: &AppParams) -> anyhow::Result<()> {
-    let (_manifest, project) = app_params.get_project();
+/// Commit of one repository on one side of manifest-diff
+enum DiffSide {
+    Commit(git2::Oid),
+    /// not in the lockfile of this side
+    Absent,
+}
 
-    let dest = app_params.get_destpath_create();
+/// Resolve one side of manifest-diff for a repository, from a lockfile or a revision
+fn diff_side(
+    repo: &Repository,
+    remote_name: &str,
+    lock: Option<&Lock>,
+    rev: &str,
+    name: &str,
+) -> anyhow::Result<DiffSide> {
+    match lock {
+        Some(lock) => match lock.repos.get(name) {
+            Some(locked) => Ok(DiffSide::Commit(git2::Oid::from_str(&locked.commit)?)),
+            None => Ok(DiffSide::Absent),
+        },
+        None => match resolve_rev(repo, remote_name, rev) {
+            Some(oid) => Ok(DiffSide::Commit(
+                repo.find_object(oid, None)?.peel_to_commit()?.id(),
+            )),
+            None => anyhow::bail!("revision {} not found in repository {}", rev, name),
+        },
+    }
+}
 
-    let mut synced = BTreeSet::new();
+/// Commits, file changes and ancestry between two commits of a repository
+fn diff_stats(repo: &Repository, a: git2::Oid, b: git2::Oid) -> anyhow::Result<String> {
+    let tree_a = repo.find_commit(a)?.tree()?;
+    let tree_b = repo.find_commit(b)?.tree()?;
+    let stats = repo
+        .diff_tree_to_tree(Some(&tree_a), Some(&tree_b), None)?
+        .stats()?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(b)?;
+    revwalk.hide(a)?;
+    let commits = revwalk.count();
+    let ancestor = a == b || repo.graph_descendant_of(b, a)?;
+    Ok(format!(
+        "{:>7} {:>5} {:>6} {:>6} {}",
+        commits,
+        stats.files_changed(),
+        format!("+{}", stats.insertions()),
+        format!("-{}", stats.deletions()),
+        if ancestor {
+            ansi_term::Color::Green.paint("yes")
+        } else {
+            ansi_term::Color::Red.paint("no")
+        }
+    ))
+}
 
-    let number_root_repos = project.repos.len();
+fn short_oid(side: &DiffSide) -> String {
+    match side {
+        DiffSide::Commit(oid) => oid.to_string()[..10].to_string(),
+        DiffSide::Absent => "-".to_string(),
+    }
+}
 
-    for (repo_nb, repo_path) in project.repos.iter().enumerate() {
-        let p = Path::new(&repo_path);
-        let dest_repo = match p.file_name() {
-            None => {
-                println!(
-                    "[{}/{}] {} {}",
-                    repo_nb + 1,
-                    ansi_term::Color::Yellow.paint("ignoring"),
-                    number_root_repos,
-                    repo_path
-                );
-                continue;
+/// Compare every repository between two sides, each a tag, a branch or a lockfile
+pub fn manifest_diff(
+    app_params: &AppParams,
+    a: &str,
+    b: &str,
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    let read_lock = |side: &str| -> anyhow::Result<Option<Lock>> {
+        if Path::new(side).is_file() {
+            Ok(Some(Lock::from_file(side)?))
+        } else {
+            Ok(None)
+        }
+    };
+    let lock_a = read_lock(a)?;
+    let lock_b = read_lock(b)?;
+
+    println!(
+        "{:40} {:10} {:10} {:>7} {:>5} {:>6} {:>6} ancestor",
+        "repository", "a", "b", "commits", "files", "added", "removed"
+    );
+    let names = on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
+
+        let sides = diff_side(&repo, remote_name, lock_a.as_ref(), a, name).and_then(|side_a| {
+            Ok((
+                side_a,
+                diff_side(&repo, remote_name, lock_b.as_ref(), b, name)?,
+            ))
+        });
+        let (side_a, side_b) = match sides {
+            Ok(sides) => sides,
+            Err(e) if continue_on_fail => {
+                repo_report_error(name, &e.to_string());
+                return Ok(name.to_string());
             }
-            Some(s) => {
-                let mut x = dest.to_path_buf();
-                x.push(s);
-                x
+            Err(e) => return Err(e),
+        };
+        let row = format!(
+            "{:40} {:10} {:10}",
+            name,
+            short_oid(&side_a),
+            short_oid(&side_b)
+        );
+        match (side_a, side_b) {
+            (DiffSide::Commit(oid_a), DiffSide::Commit(oid_b)) => {
+                match diff_stats(&repo, oid_a, oid_b) {
+                    Ok(stats) => println!("{} {}", row, stats),
+                    Err(e) => {
+                        if continue_on_fail {
+                            repo_report_error(name, &format!("cannot compare: {}", e));
+                            return Ok(name.to_string());
+                        }
+                        anyhow::bail!("{}: cannot compare: {}", name, e)
+                    }
+                }
+            }
+            (DiffSide::Absent, DiffSide::Commit(_)) => {
+                println!("{} {}", row, ansi_term::Color::Green.paint("added"))
+            }
+            (DiffSide::Commit(_), DiffSide::Absent) => {
+                println!("{} {}", row, ansi_term::Color::Red.paint("removed"))
+            }
+            (DiffSide::Absent, DiffSide::Absent) => {
+                println!(
+                    "{} {}",
+                    row,
+                    ansi_term::Color::Yellow.paint("missing on both sides")
+                )
             }
+        }
+        Ok(name.to_string())
+    })?;
+
+    // repositories of the lockfiles that are not part of the project anymore
+    let mut others = BTreeSet::new();
+    for lock in lock_a.iter().chain(lock_b.iter()) {
+        others.extend(lock.repos.keys().filter(|name| !names.contains(name)));
+    }
+    for name in others {
+        let side = |lock: &Option<Lock>| match lock.as_ref().and_then(|l| l.repos.get(name)) {
+            Some(locked) => locked.commit[..10].to_string(),
+            None => "-".to_string(),
         };
-        let url = format!("{}{}", project.prefix, repo_path);
+        let flag = match (
+            lock_a.as_ref().and_then(|l| l.repos.get(name)),
+            lock_b.as_ref().and_then(|l| l.repos.get(name)),
+        ) {
+            (None, Some(_)) => ansi_term::Color::Green.paint("added, not in project"),
+            (Some(_), None) => ansi_term::Color::Red.paint("removed, not in project"),
+            _ => ansi_term::Color::Yellow.paint("not in project"),
+        };
+        println!(
+            "{:40} {:10} {:10} {}",
+            name,
+            side(&lock_a),
+            side(&lock_b),
+            flag
+        );
+    }
+    Ok(())
+}
 
-        synced.insert(dest_repo.clone());
-        let repo = if dest_repo.exists() {
-            println!(
-                "[{}/{}] {} {:?} at {:?}",
-                repo_nb + 1,
-                number_root_repos,
-                ansi_term::Color::Green.paint("syncing"),
-                url,
-                dest_repo
-            );
-            let repo = Repository::open(&dest_repo).expect("repo working");
+/// Write the tree of every repository at a revision into a single archive,
+/// along with a lock manifest of the commits used
+pub fn manifest_archive(
+    app_params: &AppParams,
+    rev: &str,
+    output: &Path,
+    submodules: bool,
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    let mut archive = Archive::create(output)?;
+    let mut lock = Lock {
+        rev: Some(rev.to_string()),
+        ..Lock::default()
+    };
+    let mut lock_mtime = 0;
+
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
+
+        let commit = match resolve_rev(&repo, remote_name, rev) {
+            Some(oid) => repo.find_object(oid, None)?.peel_to_commit()?,
+            None => {
+                if continue_on_fail {
+                    repo_report_error(name, &format!("revision {} is missing", rev));
+                    return Ok(());
+                }
+                anyhow::bail!("{}: revision {} is missing", name, rev)
+            }
+        };
+        let mtime = commit.time().seconds();
+        let workdir = if submodules { repo.workdir() } else { None };
+        if let Err(e) = archive.add_tree(&repo, &commit.tree()?, name, mtime, workdir) {
+            if continue_on_fail {
+                repo_report_error(name, &format!("archiving failed: {}", e));
+                return Ok(());
+            }
+            anyhow::bail!("{}: archiving failed: {}", name, e)
+        }
+        lock_mtime = lock_mtime.max(mtime);
+        lock.repos.insert(
+            name.to_string(),
+            LockedRepo {
+                url: repo_entry.url(project),
+                commit: commit.id().to_string(),
+            },
+        );
+        println!(
+            "{:40} : {} {}",
+            name,
+            ansi_term::Color::Green.paint("archived"),
+            commit.id()
+        );
+        Ok(())
+    })?;
+
+    archive.add_file(LOCK_FILE_NAME, 0o644, lock_mtime, lock.to_toml().as_bytes())?;
+    archive.finish()?;
+    println!("archive written to {}", output.display());
+    Ok(())
+}
+
+/// Write a git bundle of every repository into a directory, along with an index.
+///
+/// The bundles are incremental from `since`, a lockfile or else a revision
+/// resolved like in manifest-changelog, for the repositories having it.
+pub fn manifest_bundle_create(
+    app_params: &AppParams,
+    output: &Path,
+    since: Option<&str>,
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    let lock = match since {
+        Some(since) if Path::new(since).is_file() => Some(Lock::from_file(since)?),
+        _ => None,
+    };
+    std::fs::create_dir_all(output)?;
+    let mut index = BundleIndex {
+        since: since.map(|s| s.to_string()),
+        ..BundleIndex::default()
+    };
+
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
+
+        let basis = match (since, &lock) {
+            (None, _) => None,
+            (Some(_), Some(lock)) => lock.repos.get(name).map(|l| l.commit.clone()),
+            (Some(rev), None) => match resolve_rev(&repo, remote_name, rev) {
+                Some(oid) => Some(
+                    repo.find_object(oid, None)?
+                        .peel_to_commit()?
+                        .id()
+                        .to_string(),
+                ),
+                None => None,
+            },
+        };
+        if let (Some(since), None) = (since, &basis) {
+            println!(
+                "{:40} : {}",
+                name,
+                ansi_term::Color::Yellow
+                    .paint(format!("{} not found, bundling the whole history", since))
+            )
+        }
+        let prerequisites = basis.into_iter().collect::<Vec<_>>();
+
+        let bundle_name = format!("{}.bundle", name);
+        let head = repo
+            .find_reference(&format!("refs/remotes/{}/HEAD", remote_name))
+            .ok()
+            .and_then(|r| r.symbolic_target().map(|s| s.to_string()))
+            .and_then(|s| {
+                s.strip_prefix(&format!("refs/remotes/{}/", remote_name))
+                    .map(|s| s.to_string())
+            })
+            .or_else(|| githelp::current_branch(&repo));
+
+        match bundle::create(
+            repo.path(),
+            &output.join(&bundle_name),
+            remote_name,
+            &prerequisites,
+        ) {
+            Ok(bundle::Created::Written) => {
+                println!(
+                    "{:40} : {} {}",
+                    name,
+                    ansi_term::Color::Green.paint("bundled"),
+                    if prerequisites.is_empty() {
+                        "full"
+                    } else {
+                        "incremental"
+                    }
+                );
+                index.repos.insert(
+                    name.to_string(),
+                    BundleEntry {
+                        bundle: bundle_name,
+                        remote_name: remote_name.to_string(),
+                        head,
+                        prerequisites,
+                    },
+                );
+            }
+            Ok(bundle::Created::Empty) => println!(
+                "{:40} : {}",
+                name,
+                ansi_term::Color::Green.paint("unchanged, no bundle")
+            ),
+            Err(e) => {
+                if continue_on_fail {
+                    repo_report_error(name, &format!("bundling failed: {}", e));
+                    return Ok(());
+                }
+                anyhow::bail!("{}: bundling failed: {}", name, e)
+            }
+        }
+        Ok(())
+    })?;
+
+    index.write(output)?;
+    println!("bundles written to {}", output.display());
+    Ok(())
+}
+
+/// Fetch from the bundles of a directory into the repositories of the output
+/// directory, creating the missing ones
+pub fn manifest_bundle_apply(
+    app_params: &AppParams,
+    input: &Path,
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    let index = BundleIndex::from_dir(input)?;
+    let (_manifest, project) = app_params.get_project();
+    let dest = app_params.get_destpath_create();
+
+    for (repo_entry, dest_path) in project.destinations()? {
+        let name = dest_path.to_str().expect("git with valid UTF8");
+        let entry = match index.repos.get(name) {
+            Some(entry) => entry,
+            None => {
+                println!(
+                    "{:40} : {}",
+                    name,
+                    ansi_term::Color::Yellow.paint("no bundle")
+                );
+                continue;
+            }
+        };
+        let bundle_path = input.join(&entry.bundle);
+        let remote_name = app_params.remote_name(&project, repo_entry);
+        let dest_repo = dest.join(&dest_path);
+
+        let result = if dest_repo.exists() {
+            let repo = Repository::open(&dest_repo).expect("git repository");
+            bundle::fetch(repo.path(), &bundle_path, &entry.remote_name, remote_name)
+                .map(|_| "fetched")
+        } else {
+            bundle_clone(
+                &project,
+                repo_entry,
+                &dest_repo,
+                &bundle_path,
+                entry,
+                remote_name,
+            )
+            .map(|_| "cloned")
+        };
+        match result {
+            Ok(action) => println!(
+                "{:40} : {} from {}",
+                name,
+                ansi_term::Color::Green.paint(action),
+                entry.bundle
+            ),
+            Err(e) => {
+                if continue_on_fail {
+                    repo_report_error(name, &format!("applying bundle failed: {}", e));
+                    continue;
+                }
+                anyhow::bail!("{}: applying bundle failed: {}", name, e)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create a repository from a bundle, with the remotes of the manifest
+/// and the default branch checked out
+fn bundle_clone(
+    project: &Project,
+    repo_entry: &Repo,
+    dest_repo: &Path,
+    bundle_path: &Path,
+    entry: &BundleEntry,
+    remote_name: &str,
+) -> anyhow::Result<()> {
+    if let Some(parent) = dest_repo.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let repo = Repository::init(dest_repo)?;
+    git_setup_remotes(&repo, &repo_entry.remotes(project)?)?;
+    bundle::fetch(repo.path(), bundle_path, &entry.remote_name, remote_name)?;
+    if let Some(head) = &entry.head {
+        let upstream = format!("{}/{}", remote_name, head);
+        let commit = githelp::remote_resolve_branch(&repo, remote_name, head)
+            .map_err(|e| anyhow::anyhow!("{} {}", upstream, e))?;
+        let mut branch = repo.branch(head, &commit, false)?;
+        branch.set_upstream(Some(&upstream))?;
+        githelp::checkout_branch(&repo, head, false)?;
+    }
+    Ok(())
+}
+
+/// Push the references of every repository to `<prefix><path>`, by default
+/// the branches of the remote and all the tags
+pub fn manifest_mirror(
+    app_params: &AppParams,
+    to_prefix: &str,
+    refspecs: Option<&[String]>,
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        let url = format!("{}{}", to_prefix, repo_entry.path);
+
+        if app_params.offline {
+            println!(
+                "{:40} : {} {}",
+                name,
+                ansi_term::Color::Yellow.paint("offline, would push to"),
+                url
+            );
+            return Ok(());
+        }
+
+        let default_refspecs = [
+            format!("refs/remotes/{}/*:refs/heads/*", remote_name),
+            "refs/tags/*:refs/tags/*".to_string(),
+        ];
+        let refspecs = refspecs.unwrap_or(&default_refspecs);
+        let statuses = match git_push_to(app_params, &url, &repo, PushSpecifier::Refspecs(refspecs))
+        {
+            Ok(statuses) => statuses,
+            Err(e) => {
+                if continue_on_fail {
+                    repo_report_error(name, &format!("pushing to {} failed: {}", url, e));
+                    return Ok(());
+                }
+                anyhow::bail!("{}: pushing to {} failed: {}", name, url, e)
+            }
+        };
+
+        let count = |f: fn(&PushStatus) -> bool| statuses.iter().filter(|(_, s)| f(s)).count();
+        let updated = count(|s| matches!(s, PushStatus::Updated));
+        let unchanged = count(|s| matches!(s, PushStatus::Unchanged));
+        let rejected = count(|s| matches!(s, PushStatus::Rejected(_)));
+        println!(
+            "{:40} : {} {} unchanged {}",
+            name,
+            ansi_term::Color::Green.paint(format!("{} updated", updated)),
+            unchanged,
+            if rejected > 0 {
+                ansi_term::Color::Red.paint(format!("{} rejected", rejected))
+            } else {
+                ansi_term::Style::new().paint(format!("{} rejected", rejected))
+            }
+        );
+        report_push_rejected(name, &statuses);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Options of manifest-grep
+pub struct GrepOptions {
+    pub regex: regex::bytes::Regex,
+    /// search this revision instead of the working tree
+    pub rev: Option<String>,
+    /// only search the files matching one of these patterns, or inside these directories
+    pub paths: Vec<glob::Pattern>,
+    /// number of repositories searched in parallel
+    pub jobs: usize,
+}
+
+struct GrepMatch {
+    path: String,
+    line: usize,
+    text: String,
+}
+
+/// Search a pattern in the tracked files of every repository, or in a revision
+pub fn manifest_grep(app_params: &AppParams, options: &GrepOptions) -> anyhow::Result<()> {
+    let (_manifest, project) = app_params.get_project();
+    let out_dir = app_params.get_destpath();
+
+    let repos = project
+        .destinations()?
+        .into_iter()
+        .map(|(repo_entry, dest)| {
+            let name = dest.to_str().expect("git with valid UTF8").to_string();
+            let remote_name = app_params.remote_name(&project, repo_entry).to_string();
+            (name, out_dir.join(dest), remote_name)
+        })
+        .collect::<Vec<_>>();
+
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results = repos
+        .iter()
+        .map(|_| std::sync::Mutex::new(None))
+        .collect::<Vec<_>>();
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let (_, dest_repo, remote_name) = match repos.get(i) {
+                    Some(repo) => repo,
+                    None => break,
+                };
+                let result = grep_repo(dest_repo, remote_name, options);
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    for ((name, _, _), result) in repos.iter().zip(results) {
+        match result
+            .into_inner()
+            .unwrap()
+            .expect("all repositories searched")
+        {
+            Ok(matches) => {
+                for m in matches {
+                    println!(
+                        "{}:{}:{}",
+                        ansi_term::Color::Purple.paint(format!("{}/{}", name, m.path)),
+                        ansi_term::Color::Green.paint(m.line.to_string()),
+                        m.text
+                    )
+                }
+            }
+            Err(e) => repo_report_error(name, &format!("search failed: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+fn grep_repo(
+    dest_repo: &Path,
+    remote_name: &str,
+    options: &GrepOptions,
+) -> anyhow::Result<Vec<GrepMatch>> {
+    let repo = Repository::open(dest_repo)?;
+    let mut out = Vec::new();
+    let path_matches = |path: &str| {
+        options.paths.is_empty()
+            || options
+                .paths
+                .iter()
+                .any(|p| p.matches(path) || Path::new(path).starts_with(p.as_str()))
+    };
+
+    match &options.rev {
+        None => {
+            let workdir = repo
+                .workdir()
+                .ok_or_else(|| anyhow::anyhow!("bare repository"))?;
+            for entry in repo.index()?.iter() {
+                let path = String::from_utf8_lossy(&entry.path).to_string();
+                // only regular files, no symbolic links nor submodules
+                if entry.mode & 0o170000 != 0o100000 || !path_matches(&path) {
+                    continue;
+                }
+                // deleted in the working tree
+                if let Ok(content) = std::fs::read(workdir.join(&path)) {
+                    grep_content(&options.regex, &path, &content, &mut out)
+                }
+            }
+        }
+        Some(rev) => {
+            let oid = resolve_rev(&repo, remote_name, rev)
+                .or_else(|| repo.revparse_single(rev).ok().map(|o| o.id()))
+                .ok_or_else(|| anyhow::anyhow!("revision {} is missing", rev))?;
+            let tree = repo.find_object(oid, None)?.peel_to_tree()?;
+            let mut error = None;
+            tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+                if entry.kind() != Some(git2::ObjectType::Blob) || entry.filemode() == 0o120000 {
+                    return git2::TreeWalkResult::Ok;
+                }
+                let path = format!("{}{}", root, entry.name().unwrap_or_default());
+                if !path_matches(&path) {
+                    return git2::TreeWalkResult::Ok;
+                }
+                match repo.find_blob(entry.id()) {
+                    Ok(blob) => {
+                        grep_content(&options.regex, &path, blob.content(), &mut out);
+                        git2::TreeWalkResult::Ok
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        git2::TreeWalkResult::Abort
+                    }
+                }
+            })?;
+            if let Some(e) = error {
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn grep_content(regex: &regex::bytes::Regex, path: &str, content: &[u8], out: &mut Vec<GrepMatch>) {
+    // skip binary files, like git does
+    if content[..content.len().min(8000)].contains(&0) {
+        return;
+    }
+    for (i, line) in content.split(|b| *b == b'\n').enumerate() {
+        if regex.is_match(line) {
+            out.push(GrepMatch {
+                path: path.to_string(),
+                line: i + 1,
+                text: String::from_utf8_lossy(line).trim_end().to_string(),
+            })
+        }
+    }
+}
+
+/// Options of manifest-log, every filter being optional
+#[derive(Default)]
+pub struct LogOptions {
+    /// only the commits in `rev1..rev2`, instead of the whole history
+    pub range: Option<(String, String)>,
+    pub message: Option<regex::Regex>,
+    /// matched against the author name and email
+    pub author: Option<regex::Regex>,
+    /// seconds since the epoch
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// only the commits changing these paths
+    pub paths: Vec<String>,
+    pub max_count: Option<usize>,
+}
+
+struct LogEntry {
+    time: i64,
+    repo: String,
+    id: git2::Oid,
+    author: String,
+    summary: String,
+}
+
+/// Show the commits of every repository matching some filters, merged and sorted by time
+pub fn manifest_log(
+    app_params: &AppParams,
+    options: &LogOptions,
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+        report_offline(app_params, name, &repo);
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        match &options.range {
+            None => {
+                revwalk.push_glob("refs/heads/*")?;
+                revwalk.push_glob(&format!("refs/remotes/{}/*", remote_name))?;
+                revwalk.push_glob("refs/tags/*")?;
+            }
+            Some((rev1, rev2)) => {
+                let resolve = |rev: &str| -> anyhow::Result<Option<git2::Oid>> {
+                    match resolve_rev(&repo, remote_name, rev) {
+                        Some(oid) => Ok(Some(repo.find_object(oid, None)?.peel_to_commit()?.id())),
+                        None => Ok(None),
+                    }
+                };
+                match (resolve(rev1)?, resolve(rev2)?) {
+                    (Some(oid1), Some(oid2)) => {
+                        revwalk.push(oid2)?;
+                        revwalk.hide(oid1)?;
+                    }
+                    (oid1, _) => {
+                        let missing = if oid1.is_none() { rev1 } else { rev2 };
+                        if continue_on_fail {
+                            repo_report_error(name, &format!("revision {} is missing", missing));
+                            return Ok(());
+                        }
+                        anyhow::bail!("{}: revision {} is missing", name, missing)
+                    }
+                }
+            }
+        }
+
+        let mut diff_options = git2::DiffOptions::new();
+        for path in options.paths.iter() {
+            diff_options.pathspec(path);
+        }
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            let time = commit.author().when().seconds();
+            if options.since.is_some_and(|since| time < since)
+                || options.until.is_some_and(|until| time >= until)
+            {
+                continue;
+            }
+            let author = commit.author();
+            let author_name = String::from_utf8_lossy(author.name_bytes()).to_string();
+            if let Some(regex) = &options.author {
+                let email = String::from_utf8_lossy(author.email_bytes());
+                if !regex.is_match(&author_name) && !regex.is_match(&email) {
+                    continue;
+                }
+            }
+            if let Some(regex) = &options.message {
+                if !regex.is_match(&String::from_utf8_lossy(commit.message_bytes())) {
+                    continue;
+                }
+            }
+            if !options.paths.is_empty() {
+                let parent_tree = match commit.parents().next() {
+                    Some(parent) => Some(parent.tree()?),
+                    None => None,
+                };
+                let diff = repo.diff_tree_to_tree(
+                    parent_tree.as_ref(),
+                    Some(&commit.tree()?),
+                    Some(&mut diff_options),
+                )?;
+                if diff.deltas().len() == 0 {
+                    continue;
+                }
+            }
+            entries.push(LogEntry {
+                time,
+                repo: name.to_string(),
+                id: commit.id(),
+                author: author_name,
+                summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default())
+                    .to_string(),
+            })
+        }
+        Ok(())
+    })?;
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.time));
+    for entry in entries.iter().take(options.max_count.unwrap_or(usize::MAX)) {
+        println!(
+            "{} {} {} {} : {}",
+            ansi_term::Color::Blue.paint(util::format_date(entry.time)),
+            ansi_term::Color::Purple.paint(format!("{:20}", entry.repo)),
+            ansi_term::Color::Yellow.paint(&entry.id.to_string()[..10]),
+            entry.author,
+            entry.summary
+        )
+    }
+    Ok(())
+}
+
+/// Create a topic branch in some repositories from their upstream branch,
+/// by default the upstream of the checked out branch, and check it out
+pub fn manifest_start(
+    app_params: &AppParams,
+    topic: &str,
+    repos: &[&str],
+    from: Option<&str>,
+) -> anyhow::Result<()> {
+    let out_dir = app_params.get_destpath();
+    let mut topics = Topics::load(&out_dir)?;
+    if topics.topics.contains_key(topic) {
+        anyhow::bail!("topic {} already started", topic)
+    }
+
+    let (_manifest, project) = app_params.get_project();
+    let destinations = project.destinations()?;
+    for repo in repos {
+        if !destinations.iter().any(|(_, dest)| dest == Path::new(repo)) {
+            anyhow::bail!("repository {} is not in the project", repo)
+        }
+    }
+
+    // resolve every base first, to not leave topic branches in only some of
+    // the repositories when one of them has no upstream
+    let bases = on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        if !repos.contains(&name) {
+            return Ok(None);
+        }
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+
+        let upstream = match from {
+            Some(branch) => format!("{}/{}", remote_name, branch),
+            None => githelp::current_branch(&repo)
+                .and_then(|branch| githelp::upstream_state(&repo, &branch))
+                .map(|state| state.upstream_name)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("{}: checked out branch has no upstream, use --from", name)
+                })?,
+        };
+        let base = repo
+            .find_branch(&upstream, BranchType::Remote)
+            .and_then(|b| b.get().peel_to_commit())
+            .map_err(|e| anyhow::anyhow!("{}: cannot resolve {}: {}", name, upstream, e))?;
+        if repo.find_branch(topic, BranchType::Local).is_ok() {
+            anyhow::bail!("{}: branch {} already exists", name, topic)
+        }
+        Ok(Some((name.to_string(), dest_repo, upstream, base.id())))
+    })?;
+
+    let mut started = Topic::default();
+    let mut result = Ok(());
+    for (name, dest_repo, upstream, base) in bases.into_iter().flatten() {
+        let created = Repository::open(&dest_repo).and_then(|repo| {
+            let mut branch = repo.branch(topic, &repo.find_commit(base)?, false)?;
+            started.repos.insert(name.clone(), upstream.clone());
+            branch.set_upstream(Some(&upstream))?;
+            githelp::checkout_branch(&repo, topic, false)
+        });
+        if let Err(e) = created {
+            result = Err(anyhow::anyhow!("{}: cannot start {}: {}", name, topic, e));
+            break;
+        }
+        println!(
+            "{:40} : {} {} from {}",
+            name,
+            ansi_term::Color::Green.paint("started"),
+            topic,
+            upstream
+        );
+    }
+
+    // the branches created before a failure stay part of the topic, to be
+    // finished like the others
+    if !started.repos.is_empty() {
+        topics.topics.insert(topic.to_string(), started);
+        topics.save(&out_dir)?;
+    }
+    result
+}
+
+fn load_topic(out_dir: &Path, topic: &str) -> anyhow::Result<(Topics, Topic)> {
+    let mut topics = Topics::load(out_dir)?;
+    let started = topics
+        .topics
+        .remove(topic)
+        .ok_or_else(|| anyhow::anyhow!("topic {} not started", topic))?;
+    Ok((topics, started))
+}
+
+/// Push the branch of a topic in all its repositories
+pub fn manifest_push_topic(
+    app_params: &AppParams,
+    topic: &str,
+    skip_push: bool,
+) -> anyhow::Result<()> {
+    let (_topics, started) = load_topic(&app_params.get_destpath(), topic)?;
+
+    on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        if !started.repos.contains_key(name) {
+            return Ok(());
+        }
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let remote_name = app_params.remote_name(project, repo_entry);
+
+        if skip_push || app_params.offline {
+            println!(
+                "git --git-dir={}/.git push {} {}",
+                dest_repo.display(),
+                remote_name,
+                topic
+            );
+            return Ok(());
+        }
+        let statuses = git_push_to(app_params, remote_name, &repo, PushSpecifier::Branch(topic))?;
+        if statuses
+            .iter()
+            .any(|(_, s)| matches!(s, PushStatus::Rejected(_)))
+        {
+            report_push_rejected(name, &statuses);
+        } else {
+            println!(
+                "{:40} : {} {} to {}",
+                name,
+                ansi_term::Color::Green.paint("pushed"),
+                topic,
+                remote_name
+            );
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Delete the branch of a topic in the repositories where it's merged into
+/// its upstream, forgetting the topic once deleted everywhere
+pub fn manifest_finish(app_params: &AppParams, topic: &str, force: bool) -> anyhow::Result<()> {
+    let out_dir = app_params.get_destpath();
+    let (mut topics, mut started) = load_topic(&out_dir, topic)?;
+
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let upstream = match started.repos.get(name) {
+            Some(upstream) => upstream.clone(),
+            None => return Ok(()),
+        };
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        report_offline(app_params, name, &repo);
+
+        let mut branch = match repo.find_branch(topic, BranchType::Local) {
+            Ok(branch) => branch,
+            Err(_) => {
+                println!(
+                    "{:40} : {}",
+                    name,
+                    ansi_term::Color::Yellow.paint("branch already deleted")
+                );
+                started.repos.remove(name);
+                return Ok(());
+            }
+        };
+        let head = branch.get().peel_to_commit()?.id();
+        let base = repo
+            .find_branch(&upstream, BranchType::Remote)?
+            .get()
+            .peel_to_commit()?
+            .id();
+        let merged = head == base || repo.graph_descendant_of(base, head)?;
+        if !merged && !force {
+            println!(
+                "{:40} : {} into {}, keeping {}",
+                name,
+                ansi_term::Color::Red.paint("not merged"),
+                upstream,
+                topic
+            );
+            return Ok(());
+        }
+
+        if branch.is_head() {
+            // back on the local branch of the upstream, or detached on it
+            let local = upstream
+                .split_once('/')
+                .map(|(_, b)| b)
+                .filter(|b| repo.find_branch(b, BranchType::Local).is_ok());
+            match local {
+                Some(local) => githelp::checkout_branch(&repo, local, force)?,
+                None => {
+                    let commit = repo.find_commit(base)?;
+                    let mut checkout = git2::build::CheckoutBuilder::new();
+                    if force {
+                        checkout.force();
+                    }
+                    repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+                    repo.set_head_detached(base)?;
+                }
+            }
+        }
+        branch.delete()?;
+        println!(
+            "{:40} : {} {}",
+            name,
+            ansi_term::Color::Green.paint(if merged { "merged, deleted" } else { "deleted" }),
+            topic
+        );
+        started.repos.remove(name);
+        Ok(())
+    })?;
+
+    if started.repos.is_empty() {
+        println!("topic {} finished", topic);
+    } else {
+        topics.topics.insert(topic.to_string(), started);
+    }
+    topics.save(&out_dir)
+}
+
+/// Show the checked out branch of every repository, along its topics and state
+pub fn manifest_status(app_params: &AppParams) -> anyhow::Result<()> {
+    let topics = Topics::load(&app_params.get_destpath())?;
+
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+
+        let mut status = match githelp::current_branch(&repo) {
+            Some(branch) => {
+                let mut status = ansi_term::Color::Green.paint(&branch).to_string();
+                if let Some(state) = githelp::upstream_state(&repo, &branch) {
+                    status.push_str(&format!(" [{}", state.upstream_name));
+                    if state.ahead > 0 {
+                        status.push_str(&format!(" ahead {}", state.ahead));
+                    }
+                    if state.behind > 0 {
+                        status.push_str(&format!(" behind {}", state.behind));
+                    }
+                    status.push(']');
+                }
+                status
+            }
+            None => ansi_term::Color::Yellow.paint("detached").to_string(),
+        };
+        let repo_topics = topics.topics_of(name);
+        if !repo_topics.is_empty() {
+            status.push_str(&format!(
+                " topic {}",
+                ansi_term::Color::Blue.paint(repo_topics.join(","))
+            ));
+        }
+        if githelp::is_dirty(&repo) {
+            status.push_str(&format!(" {}", ansi_term::Color::Red.paint("dirty")));
+        }
+        println!("{:40} : {}", name, status);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Trailer identifying the commits made together by manifest-commit
+pub const CHANGE_SET_TRAILER: &str = "Change-Set-Id";
+
+/// Get a new change set id and the commit message with its trailer
+fn change_set_message(message: &str) -> (String, String) {
+    let change_set_id = format!(
+        "{:016x}",
+        util::fnv1a64(
+            format!(
+                "{}{:?}{}",
+                message,
+                std::time::SystemTime::now(),
+                std::process::id()
+            )
+            .as_bytes()
+        )
+    );
+    let message = format!(
+        "{}\n\n{}: {}\n",
+        message.trim_end(),
+        CHANGE_SET_TRAILER,
+        change_set_id
+    );
+    (change_set_id, message)
+}
+
+/// Commit the staged changes of every repository, or all the changes of the tracked
+/// files with `all` as `git commit -a` does, with the same message and a shared
+/// change set trailer
+pub fn manifest_commit(
+    app_params: &AppParams,
+    message: &str,
+    all: bool,
+    include_untracked: bool,
+) -> anyhow::Result<()> {
+    let (change_set_id, message) = change_set_message(message);
+
+    let mut committed = 0;
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let repo = Repository::open(&dest_repo).expect("git repository");
+        let mut index = repo.index()?;
+        if all {
+            if include_untracked {
+                index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+            }
+            index.update_all(["*"].iter(), None)?;
+            index.write()?;
+        }
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let parent = match repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+        if parent.as_ref().map(|p| p.tree_id()) == Some(tree.id()) {
+            println!("{:40} : no changes", name);
+            return Ok(());
+        }
+
+        let signature = repo.signature()?;
+        let parents = parent.iter().collect::<Vec<_>>();
+        let oid = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &parents,
+        )?;
+        println!(
+            "{:40} : {} {}",
+            name,
+            ansi_term::Color::Green.paint("committed"),
+            oid
+        );
+        committed += 1;
+        Ok(())
+    })?;
+
+    if committed > 0 {
+        println!("{}: {}", CHANGE_SET_TRAILER, change_set_id);
+    }
+    Ok(())
+}
+
+/// Marker of the stash messages, to only pop the stashes mugit created
+const MUGIT_STASH_MARKER: &str = "mugit-stash";
+
+/// Tell whether a stash message, as `On <branch>: <message>`, is one of mugit.
+/// Branch names can't contain a colon, so the message starts after the first one.
+fn is_mugit_stash(message: &str) -> bool {
+    message
+        .split_once(": ")
+        .is_some_and(|(_, message)| message.starts_with(MUGIT_STASH_MARKER))
+}
+
+/// Get the index and message of the stashes created by mugit, most recent first
+fn mugit_stashes(repo: &mut Repository) -> anyhow::Result<Vec<(usize, String)>> {
+    let mut stashes = Vec::new();
+    repo.stash_foreach(|index, message, _oid| {
+        if is_mugit_stash(message) {
+            stashes.push((index, message.to_string()))
+        }
+        true
+    })?;
+    Ok(stashes)
+}
+
+/// Stash the local changes of every repository, with a common message
+pub fn manifest_stash_push(
+    app_params: &AppParams,
+    message: Option<&str>,
+    include_untracked: bool,
+) -> anyhow::Result<()> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("time after the epoch")
+        .as_secs();
+    let message = format!(
+        "{} {}{}",
+        MUGIT_STASH_MARKER,
+        util::format_date(now as i64),
+        message.map(|m| format!(" {}", m)).unwrap_or_default()
+    );
+    let mut flags = git2::StashFlags::DEFAULT;
+    if include_untracked {
+        flags |= git2::StashFlags::INCLUDE_UNTRACKED;
+    }
+
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let mut repo = Repository::open(&dest_repo).expect("git repository");
+        let signature = repo.signature()?;
+        match repo.stash_save(&signature, &message, Some(flags)) {
+            Ok(oid) => println!(
+                "{:40} : {} {}",
+                name,
+                ansi_term::Color::Green.paint("stashed"),
+                oid
+            ),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                println!("{:40} : nothing to stash", name)
+            }
+            Err(e) => repo_report_error(name, &format!("stash failed: {}", e)),
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Pop the most recent stash created by mugit in every repository,
+/// keeping it when applying it conflicts
+pub fn manifest_stash_pop(app_params: &AppParams) -> anyhow::Result<()> {
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let mut repo = Repository::open(&dest_repo).expect("git repository");
+        let (index, message) = match mugit_stashes(&mut repo)?.into_iter().next() {
+            Some(stash) => stash,
+            None => {
+                println!("{:40} : no mugit stash", name);
+                return Ok(());
+            }
+        };
+        match repo.stash_pop(index, None) {
+            Ok(()) => println!(
+                "{:40} : {} {}",
+                name,
+                ansi_term::Color::Green.paint("popped"),
+                message
+            ),
+            Err(e)
+                if e.code() == git2::ErrorCode::Conflict
+                    || e.code() == git2::ErrorCode::MergeConflict =>
+            {
+                repo_report_error(
+                    name,
+                    &format!("conflicts, stash@{{{}}} kept: {}", index, e.message()),
+                )
+            }
+            Err(e) => repo_report_error(name, &format!("stash pop failed: {}", e)),
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// List the stashes created by mugit in every repository
+pub fn manifest_stash_list(app_params: &AppParams) -> anyhow::Result<()> {
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let mut repo = Repository::open(&dest_repo).expect("git repository");
+        for (index, message) in mugit_stashes(&mut repo)? {
+            println!("{:40} : stash@{{{}}} {}", name, index, message)
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Change the requirement of a dependency in the Cargo.toml files of every repository,
+/// optionally committing them with a shared change set trailer. The Cargo.lock files
+/// are not updated.
+pub fn manifest_bump_dep(
+    app_params: &AppParams,
+    krate: &str,
+    version: &str,
+    dry_run: bool,
+    commit_message: Option<&str>,
+) -> anyhow::Result<()> {
+    semver::VersionReq::parse(version)
+        .map_err(|e| anyhow::anyhow!("invalid version {}: {}", version, e))?;
+    let change_set = commit_message.map(change_set_message);
+
+    let changed = on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        let mut changed = Vec::new();
+        for path in cargo::workspace_manifests(&dest_repo) {
+            let content = std::fs::read_to_string(&path)?;
+            let mut doc = content
+                .parse::<toml_edit::DocumentMut>()
+                .map_err(|e| anyhow::anyhow!("{:?}: {}", path, e))?;
+            let bumps = cargo::bump_dependency(&mut doc, krate, version);
+            if bumps.is_empty() {
+                continue;
+            }
+            let relative = path
+                .strip_prefix(&dest_repo)
+                .expect("manifest in repository")
+                .to_path_buf();
+            let target = format!("{}/{}", name, relative.to_str().expect("utf8 path"));
+            for bump in bumps.iter() {
+                println!(
+                    "{:40} : [{}] {} {} -> {}",
+                    target,
+                    bump.table,
+                    krate,
+                    ansi_term::Color::Red.paint(&bump.old),
+                    ansi_term::Color::Green.paint(&bump.new)
+                );
+            }
+            if !dry_run {
+                std::fs::write(&path, doc.to_string())?;
+            }
+            changed.push(relative);
+        }
+        if changed.is_empty() || dry_run {
+            return Ok(!changed.is_empty());
+        }
+
+        if dest_repo.join("Cargo.lock").exists() {
+            println!(
+                "{:40} : {}",
+                name,
+                ansi_term::Color::Yellow.paint(format!(
+                    "Cargo.lock not updated, run cargo update -p {}",
+                    krate
+                ))
+            );
+        }
+        if let Some((_, message)) = &change_set {
+            let repo = Repository::open(&dest_repo).expect("git repository");
+            let oid = commit_paths(&repo, &changed, message)?;
+            println!(
+                "{:40} : {} {}",
+                name,
+                ansi_term::Color::Green.paint("committed"),
+                oid
+            );
+        }
+        Ok(true)
+    })?;
+
+    if !changed.contains(&true) {
+        println!("no dependency on {} to bump", krate);
+    } else if let (Some((change_set_id, _)), false) = (&change_set, dry_run) {
+        println!("{}: {}", CHANGE_SET_TRAILER, change_set_id);
+    }
+    Ok(())
+}
+
+/// Commit some files of the working directory on top of HEAD, leaving the other
+/// staged changes out of the commit
+fn commit_paths(repo: &Repository, paths: &[PathBuf], message: &str) -> anyhow::Result<git2::Oid> {
+    let parent = repo.head()?.peel_to_commit()?;
+    let mut tree_index = git2::Index::new()?;
+    tree_index.read_tree(&parent.tree()?)?;
+    let mut index = repo.index()?;
+    for path in paths {
+        index.add_path(path)?;
+        let entry = index
+            .get_path(path, 0)
+            .ok_or_else(|| anyhow::anyhow!("{:?} not staged", path))?;
+        tree_index.add(&entry)?;
+    }
+    index.write()?;
+    let tree = repo.find_tree(tree_index.write_tree_to(repo)?)?;
+
+    let signature = repo.signature()?;
+    Ok(repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&parent],
+    )?)
+}
+
+/// Output format of manifest-graph
+pub enum GraphFormat {
+    Text,
+    Dot,
+}
+
+/// Show the dependencies between the repositories, the dependencies first
+pub fn manifest_graph(app_params: &AppParams, format: GraphFormat) -> anyhow::Result<()> {
+    on_project(app_params, |out_dir, _manifest, project| {
+        let graph = DepGraph::build(project, out_dir)?;
+        match format {
+            GraphFormat::Dot => print!("{}", graph.to_dot()),
+            GraphFormat::Text => {
+                for name in graph.topo_order()? {
+                    let deps = graph.edges[&name]
+                        .iter()
+                        .map(|(dep, kind)| format!("{} ({})", dep, kind))
+                        .collect::<Vec<_>>();
+                    if deps.is_empty() {
+                        println!("{:40} : {}", name, ansi_term::Color::Green.paint("none"))
+                    } else {
+                        println!("{:40} : {}", name, deps.join(", "))
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Run a command in every repository, with MUGIT_REPO set to the checkout directory
+pub fn manifest_foreach(
+    app_params: &AppParams,
+    command: &[String],
+    continue_on_fail: bool,
+) -> anyhow::Result<()> {
+    // a single argument is a shell command line, several ones are run as is
+    let (program, args) = match command {
+        [line] => ("sh", vec!["-c", line.as_str()]),
+        _ => (
+            command[0].as_str(),
+            command[1..].iter().map(|a| a.as_str()).collect(),
+        ),
+    };
+    let display = command.join(" ");
+    on_project_repos(app_params, |_project, _repo_entry, dest_repo, name| {
+        println!("{:40} : {}", name, ansi_term::Color::Blue.paint(&display));
+        let status = Command::new(program)
+            .args(&args)
+            .current_dir(&dest_repo)
+            .env("MUGIT_REPO", name)
+            .status();
+        let failure = match status {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => format!("command failed, {}", status),
+            Err(e) => format!("{} failed to start: {}", program, e),
+        };
+        if continue_on_fail {
+            repo_report_error(name, &failure);
+            return Ok(());
+        }
+        anyhow::bail!("{}: {}", name, failure)
+    })?;
+    Ok(())
+}
+
+/// Name of the mugit bookkeeping file or directory in an output directory
+pub const MUGIT_STATE_NAME: &str = ".mugit";
+
+/// What to do with the directories of the output directory that are not synced repositories
+pub enum Prune {
+    /// Only list what would be pruned
+    DryRun,
+    /// Remove the directories
+    Remove,
+    /// Move the directories into an archive directory
+    Archive(PathBuf),
+}
+
+/// How to update the local branches of the already cloned repositories after fetching
+pub struct UpdateBranches {
+    /// also fast-forward the tracking branches that are not checked out
+    pub all: bool,
+    /// rebase the checked out branch when it has local commits
+    pub rebase: bool,
+}
+
+/// Options of manifest-sync
+#[derive(Default)]
+pub struct SyncOptions {
+    pub prune: Option<Prune>,
+    pub update_branches: Option<UpdateBranches>,
+    pub clone: CloneOptions,
+    /// fetch the whole history of the shallow repositories
+    pub unshallow: bool,
+    /// mirrors to update on every sync and clone from, instead of cloning from
+    /// the remotes
+    pub cache: Option<Cache>,
+}
+
+pub fn manifest_sync(app_params: &AppParams, options: SyncOptions) -> anyhow::Result<()> {
+    let (_manifest, project) = app_params.get_project();
+
+    let dest = app_params.get_destpath_create();
+
+    let mut synced = BTreeSet::new();
+
+    let destinations = project.destinations()?;
+    let number_root_repos = destinations.len();
+
+    for (repo_nb, (repo_entry, dest_path)) in destinations.into_iter().enumerate() {
+        let dest_repo = dest.join(&dest_path);
+        if let Some(parent) = dest_repo.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let name = dest_path.to_str().expect("git with valid UTF8");
+        let url = repo_entry.url(&project);
+        let remote_name = repo_entry.remote_name(&project);
+        let remotes = repo_entry.remotes(&project)?;
+        let remote_names = remotes.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+        let clone_options = options.clone.for_repo(repo_entry);
+
+        synced.insert(dest_repo.clone());
+        let repo = if dest_repo.exists() {
+            println!(
+                "[{}/{}] {} {:?} at {:?}",
+                repo_nb + 1,
+                number_root_repos,
+                ansi_term::Color::Green.paint("syncing"),
+                url,
+                dest_repo
+            );
+            let repo = Repository::open(&dest_repo).expect("repo working");
 
-            git_fetch_all(&app_params, &repo);
+            git_setup_remotes(&repo, &remotes)?;
+            let depth = if app_params.offline {
+                println!(
+                    "  {} {} ({})",
+                    ansi_term::Color::Yellow.paint("offline, would fetch"),
+                    remote_names.join(", "),
+                    last_fetch_description(&repo)
+                );
+                None
+            } else if !repo.is_shallow() {
+                Some(FetchDepth::Default)
+            } else if options.unshallow {
+                println!("  {}", ansi_term::Color::Blue.paint("unshallowing"));
+                if let Err(e) = git_fetch(app_params, &repo, remote_name, FetchDepth::Unshallow) {
+                    repo_report_error(name, &format!("unshallowing failed: {}", e))
+                }
+                Some(FetchDepth::Default)
+            } else {
+                Some(
+                    clone_options
+                        .depth
+                        .map_or(FetchDepth::Default, FetchDepth::Depth),
+                )
+            };
+            if let Some(depth) = depth {
+                // keep the mirror up to date, and marked as in use for gc
+                if let Some(cache) = &options.cache {
+                    if let Err(e) = cache.update(app_params, &url) {
+                        repo_report_error(name, &format!("updating cache failed: {}", e))
+                    }
+                }
+                git_fetch_all(app_params, &repo, name, &remote_names, depth);
+            }
+            if let Some(update_branches) = &options.update_branches {
+                update_local_branches(&repo, name, update_branches)
+            }
             repo
+        } else if app_params.offline {
+            println!(
+                "[{}/{}] {} {:?} at {:?}",
+                repo_nb + 1,
+                number_root_repos,
+                ansi_term::Color::Yellow.paint("offline, would clone"),
+                url,
+                dest_repo
+            );
+            continue;
         } else {
             println!(
                 "[{}/{}] {} {:?} at {:?}",
@@ -689,49 +2593,324 @@ pub fn manifest_sync(app_params: &AppParams) -> anyhow::Result<()> {
                 dest_repo
             );
 
-            git_clone(&app_params, &url, dest_repo.as_path())
+            let mirror =
+                options
+                    .cache
+                    .as_ref()
+                    .and_then(|cache| match cache.update(app_params, &url) {
+                        Ok(mirror) => Some(mirror),
+                        Err(e) => {
+                            repo_report_error(name, &format!("updating cache failed: {}", e));
+                            None
+                        }
+                    });
+            let cloned = match mirror {
+                None => git_clone(
+                    app_params,
+                    &url,
+                    dest_repo.as_path(),
+                    remote_name,
+                    &clone_options,
+                ),
+                Some(mirror) => {
+                    println!(
+                        "  {} {:?}",
+                        ansi_term::Color::Blue.paint("cloning from cache"),
+                        mirror
+                    );
+                    // the whole history is local, so no shallow or partial clone
+                    let mirror_options = CloneOptions {
+                        single_branch: clone_options.single_branch,
+                        ..CloneOptions::default()
+                    };
+                    let mirror = mirror.to_str().expect("cache dir is utf8");
+                    git_clone(
+                        app_params,
+                        mirror,
+                        dest_repo.as_path(),
+                        remote_name,
+                        &mirror_options,
+                    )
+                    .and_then(|repo| {
+                        repo.remote_set_url(remote_name, &url)?;
+                        Ok(repo)
+                    })
+                }
+            };
+            let repo = match cloned {
+                Ok(repo) => repo,
+                Err(e) => {
+                    repo_report_error(name, &format!("cloning failed: {}", e));
+                    continue;
+                }
+            };
+
+            let depth = match clone_options.depth {
+                Some(depth) if repo.is_shallow() => FetchDepth::Depth(depth),
+                _ => FetchDepth::Default,
+            };
+            git_setup_remotes(&repo, &remotes)?;
+            git_fetch_all(app_params, &repo, name, &remote_names[1..], depth);
+            repo
         };
 
-        for mut submodule in repo.submodules().expect("cannot update submodules") {
-            let mut sub_repo_path = p.to_path_buf();
-            sub_repo_path.push(submodule.url().expect("submodule has url"));
-            let sub_repo_path = util::path_canon(&sub_repo_path);
+        sync_submodules(
+            app_params,
+            &repo,
+            &url,
+            Path::new(""),
+            repo_entry,
+            &dest_repo,
+        );
+    }
+
+    // the archive directory may be given relative to the current directory,
+    // keep its path inside the destination to not prune it into itself
+    let archive_in_dest = match &options.prune {
+        Some(Prune::Archive(archive_dir)) => {
+            let archive_dir = archive_dir
+                .canonicalize()
+                .or_else(|_| std::path::absolute(archive_dir))?;
+            archive_dir
+                .strip_prefix(dest.canonicalize()?)
+                .ok()
+                .map(Path::to_path_buf)
+        }
+        _ => None,
+    };
 
-            let sub_url = format!("{}{}", project.prefix, &sub_repo_path.to_str().unwrap());
-            let mut sub_repo = dest_repo.clone();
-            sub_repo.push(submodule.path());
+    let mut unmaintained = Vec::new();
+    find_unmaintained(&dest, &synced, &mut unmaintained);
 
-            let mut sub_repo_git = sub_repo.clone();
-            sub_repo_git.push(".git");
+    for p in unmaintained {
+        if p == dest.join(MUGIT_STATE_NAME) {
+            continue;
+        }
+        if let Some(archive) = &archive_in_dest {
+            let relative = p.strip_prefix(&dest).expect("inside destination");
+            if archive.starts_with(relative) || relative.starts_with(archive) {
+                continue;
+            }
+        }
+        println!(
+            "{} : {}",
+            p.to_str().unwrap_or("non unicode path"),
+            ansi_term::Color::Red.paint("directory is not maintained by synced"),
+        );
+        if let Some(prune) = &options.prune {
+            prune_directory(&dest, &p, prune)
+        }
+    }
 
-            //
-            //submodule.update(true, None).expect("cloning submodules");
+    Ok(())
+}
 
-            if sub_repo_git.exists() {
-                println!(
-                    "  {} {:?} at {:?}",
-                    ansi_term::Color::Blue.paint("updating submodule"),
-                    sub_url,
-                    sub_repo,
-                );
-                submodule.update(false, None).expect("submodule update")
-            } else {
-                println!(
-                    "  {} {:?} at {:?}",
-                    ansi_term::Color::Blue.paint("cloning submodule"),
-                    sub_url,
-                    sub_repo,
-                );
-                let _repo = git_clone(&app_params, &sub_url, &sub_repo);
+/// Initialize, clone and update the submodules of a repository, recursively.
+///
+/// `parent` is the path of the repository relative to the root repository of
+/// the manifest, which is what the skip and pin options of the manifest refer to.
+fn sync_submodules(
+    app_params: &AppParams,
+    repo: &Repository,
+    url: &str,
+    parent: &Path,
+    repo_entry: &Repo,
+    dest_root: &Path,
+) {
+    let submodules = match repo.submodules() {
+        Ok(submodules) => submodules,
+        Err(e) => {
+            repo_report_error(
+                &parent.display().to_string(),
+                &format!("cannot list submodules: {}", e),
+            );
+            return;
+        }
+    };
+    for mut submodule in submodules {
+        let sub_path = parent.join(submodule.path());
+        let sub_name = sub_path.to_str().expect("submodule with valid UTF8");
+        let sub_dest = dest_root.join(&sub_path);
+        if repo_entry.skip_submodules.iter().any(|s| s == sub_name) {
+            println!(
+                "  {} {:?}",
+                ansi_term::Color::Yellow.paint("skipping submodule"),
+                sub_dest
+            );
+            continue;
+        }
+        if app_params.offline {
+            println!(
+                "  {} {:?}",
+                ansi_term::Color::Yellow.paint("offline, would update submodule"),
+                sub_dest
+            );
+            continue;
+        }
+        let sub_url = match submodule.url() {
+            Some(sub_url) => util::resolve_relative_url(url, sub_url),
+            None => {
+                repo_report_error(sub_name, "submodule has no url");
+                continue;
+            }
+        };
 
-                submodule
-                    .update(false, None)
-                    .expect("submodule update after clone")
+        let sub_repo = match update_submodule(app_params, repo, &mut submodule, &sub_url, &sub_dest)
+        {
+            Ok(sub_repo) => sub_repo,
+            Err(e) => {
+                repo_report_error(sub_name, &format!("submodule update failed: {}", e));
+                continue;
             }
+        };
+        if let Some(rev) = repo_entry.pin_submodules.get(sub_name) {
+            match checkout_pinned(&sub_repo, rev) {
+                Ok(oid) => println!(
+                    "  {} {:?} at {} ({})",
+                    ansi_term::Color::Blue.paint("pinned submodule"),
+                    sub_dest,
+                    rev,
+                    oid
+                ),
+                Err(e) => repo_report_error(
+                    sub_name,
+                    &format!("cannot checkout pinned revision {}: {}", rev, e),
+                ),
+            }
+        }
+        sync_submodules(
+            app_params, &sub_repo, &sub_url, &sub_path, repo_entry, dest_root,
+        );
+    }
+}
+
+/// Register a submodule with its resolved url, then clone or fetch it and
+/// checkout the commit recorded in the parent repository
+fn update_submodule(
+    app_params: &AppParams,
+    repo: &Repository,
+    submodule: &mut git2::Submodule,
+    sub_url: &str,
+    sub_dest: &Path,
+) -> anyhow::Result<Repository> {
+    let config_name = submodule
+        .name()
+        .ok_or_else(|| anyhow::anyhow!("submodule name is not utf8"))?
+        .to_string();
+    submodule.init(false)?;
+    repo.config()?
+        .set_str(&format!("submodule.{}.url", config_name), sub_url)?;
+    submodule.reload(true)?;
+
+    if sub_dest.join(".git").exists() {
+        println!(
+            "  {} {:?} at {:?}",
+            ansi_term::Color::Blue.paint("updating submodule"),
+            sub_url,
+            sub_dest,
+        );
+        submodule.sync()?;
+        let sub_repo = submodule.open()?;
+        git_fetch(app_params, &sub_repo, "origin", FetchDepth::Default)?;
+    } else {
+        println!(
+            "  {} {:?} at {:?}",
+            ansi_term::Color::Blue.paint("cloning submodule"),
+            sub_url,
+            sub_dest,
+        );
+        git_clone(
+            app_params,
+            sub_url,
+            sub_dest,
+            "origin",
+            &CloneOptions::default(),
+        )?;
+    }
+    submodule.update(false, None)?;
+    Ok(submodule.open()?)
+}
+
+/// Checkout a revision of a submodule in detached head, as a commit or a
+/// local or remote branch or tag name
+fn checkout_pinned(repo: &Repository, rev: &str) -> Result<git2::Oid, git2::Error> {
+    let object = repo
+        .revparse_single(rev)
+        .or_else(|_| repo.revparse_single(&format!("origin/{}", rev)))?;
+    let commit = object.peel_to_commit()?;
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(git2::build::CheckoutBuilder::new().safe()),
+    )?;
+    repo.set_head_detached(commit.id())?;
+    Ok(commit.id())
+}
+
+/// Fast-forward the local branches to their upstream when it's safe, reporting
+/// the ones that diverged or have local changes
+fn update_local_branches(repo: &Repository, name: &str, update: &UpdateBranches) {
+    let current = githelp::current_branch(repo);
+    let mut branches = current.iter().cloned().collect::<Vec<_>>();
+    if update.all {
+        branches.extend(
+            githelp::tracking_branches(repo)
+                .into_iter()
+                .filter(|b| Some(b) != current.as_ref()),
+        )
+    }
+
+    for branch in branches {
+        let is_current = Some(&branch) == current.as_ref();
+        let state = match githelp::upstream_state(repo, &branch) {
+            None => continue,
+            Some(state) => state,
+        };
+        if state.behind == 0 {
+            continue;
+        }
+        if is_current && githelp::is_dirty(repo) {
+            repo_report_error(
+                name,
+                &format!(
+                    "{} is behind {} but has local changes",
+                    branch, state.upstream_name
+                ),
+            );
+            continue;
+        }
+
+        let result = if state.ahead == 0 {
+            githelp::fast_forward(repo, &branch, state.upstream, is_current)
+                .map(|()| "fast-forwarded")
+        } else if is_current && update.rebase {
+            githelp::rebase_on_upstream(repo, &branch).map(|()| "rebased")
+        } else {
+            repo_report_error(
+                name,
+                &format!(
+                    "{} diverged from {} ({} ahead, {} behind)",
+                    branch, state.upstream_name, state.ahead, state.behind
+                ),
+            );
+            continue;
+        };
+        match result {
+            Ok(action) => println!(
+                "  {} {} on {} ({} behind)",
+                ansi_term::Color::Blue.paint(action),
+                branch,
+                state.upstream_name,
+                state.behind
+            ),
+            Err(e) => repo_report_error(name, &format!("updating {} failed: {}", branch, e)),
         }
     }
+}
 
-    let current_dest_content = std::fs::read_dir(dest).expect("read directory works");
+/// Find every directory that is not a synced repository, descending into the
+/// directories that contain nested synced repositories
+fn find_unmaintained(dir: &Path, synced: &BTreeSet<PathBuf>, out: &mut Vec<PathBuf>) {
+    let current_dest_content = std::fs::read_dir(dir).expect("read directory works");
 
     for entry in current_dest_content {
         match entry {
@@ -740,16 +2919,70 @@ pub fn manifest_sync(app_params: &AppParams) -> anyhow::Result<()> {
             }
             Ok(dirent) => {
                 let p = dirent.path();
-                if !synced.contains(&p) {
-                    println!(
-                        "{} : {}",
-                        p.to_str().unwrap_or("non unicode path"),
-                        ansi_term::Color::Red.paint("directory is not maintained by synced"),
-                    )
+                if synced.contains(&p) {
+                    continue;
+                }
+                if synced.iter().any(|s| s.starts_with(&p)) {
+                    find_unmaintained(&p, synced, out);
+                } else {
+                    out.push(p)
                 }
             }
         }
     }
+}
 
-    Ok(())
+/// Remove or archive an unmaintained directory, only if it's a git repository
+/// without any local change or unpushed commit
+fn prune_directory(dest: &Path, p: &Path, prune: &Prune) {
+    let report = |s: &str| println!("  {}", ansi_term::Color::Yellow.paint(s));
+
+    let repo = match Repository::open(p) {
+        Ok(repo) => repo,
+        Err(_) => return report("not a git repository, not pruning"),
+    };
+    if !repo.is_bare() && githelp::is_dirty(&repo) {
+        return report("has local changes, not pruning");
+    }
+    if githelp::has_unpushed_commits(&repo) {
+        return report("has unpushed commits, not pruning");
+    }
+    drop(repo);
+
+    let result = match prune {
+        Prune::DryRun => return report("would be pruned"),
+        Prune::Remove => std::fs::remove_dir_all(p).map(|()| "pruned".to_string()),
+        Prune::Archive(archive_dir) => {
+            let target = archive_dir.join(p.strip_prefix(dest).expect("inside destination"));
+            if target.exists() {
+                return report(&format!("archive {:?} already exists, not pruning", target));
+            }
+            target
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::rename(p, &target))
+                .map(|()| format!("archived to {:?}", target))
+        }
+    };
+    match result {
+        Ok(s) => println!("  {}", ansi_term::Color::Green.paint(s)),
+        Err(e) => println!(
+            "  {}",
+            ansi_term::Color::Red.paint(format!("pruning failed: {}", e))
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mugit_stash_messages() {
+        assert!(is_mugit_stash("On main: mugit-stash 1700000000"));
+        assert!(is_mugit_stash("On (no branch): mugit-stash 1700000000 wip"));
+        assert!(!is_mugit_stash("On main: keep the mugit-stash output"));
+        assert!(!is_mugit_stash("WIP on main: 1234567 mugit-stash"));
+        assert!(!is_mugit_stash("mugit-stash"));
+    }
 }
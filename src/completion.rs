@@ -0,0 +1,77 @@
+use clap::App;
+use clap_complete::Shell;
+
+/// Name of the binary the completions are registered for
+const BIN_NAME: &str = "mugit";
+
+/// Hidden subcommand listing the project selectors of the loaded manifests
+pub const COMPLETE_PROJECTS: &str = "complete-projects";
+
+/// clap only knows static values, so the generated completion of the project
+/// option is replaced by a call to the hidden subcommand listing the selectors
+fn dynamic_projects(shell: Shell, script: String) -> String {
+    match shell {
+        Shell::Bash => {
+            let files = "COMPREPLY=($(compgen -f \"${cur}\"))";
+            let projects = format!(
+                "COMPREPLY=($(compgen -W \"$({} {} 2>/dev/null)\" -- \"${{cur}}\"))",
+                BIN_NAME, COMPLETE_PROJECTS
+            );
+            let mut out = String::new();
+            let mut project_case = false;
+            for line in script.lines() {
+                let trimmed = line.trim();
+                if trimmed == "--project)" || trimmed == "-p)" {
+                    project_case = true;
+                } else if project_case && trimmed == files {
+                    out.push_str(&line.replace(files, &projects));
+                    out.push('\n');
+                    project_case = false;
+                    continue;
+                } else {
+                    project_case = false;
+                }
+                out.push_str(line);
+                out.push('\n');
+            }
+            out
+        }
+        Shell::Zsh => {
+            let function = format!(
+                "_mugit_projects() {{\n    local -a selectors\n    selectors=(${{(f)\"$({} {} 2>/dev/null)\"}})\n    compadd -a selectors\n}}\n\n_mugit() {{",
+                BIN_NAME, COMPLETE_PROJECTS
+            );
+            script
+                .replace(":PROJECT: '", ":PROJECT:_mugit_projects'")
+                .replacen("_mugit() {", &function, 1)
+        }
+        _ => script
+            .lines()
+            .map(|line| {
+                if line.contains(" -s p -l project ") && line.ends_with(" -r") {
+                    format!("{} -f -a \"({} {})\"\n", line, BIN_NAME, COMPLETE_PROJECTS)
+                } else {
+                    format!("{}\n", line)
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Generate the completion script of a shell from the clap definitions
+pub fn completions(mut app: App, shell: &str) -> String {
+    let shell = match shell {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        _ => Shell::Fish,
+    };
+    let mut out = Vec::new();
+    clap_complete::generate(shell, &mut app, BIN_NAME, &mut out);
+    let script = String::from_utf8(out).expect("completion script is utf8");
+    dynamic_projects(shell, script)
+}
+
+/// Write the man page generated from the clap definitions to stdout
+pub fn man(app: App) -> std::io::Result<()> {
+    clap_mangen::Man::new(app.name(BIN_NAME)).render(&mut std::io::stdout())
+}
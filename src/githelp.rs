@@ -1,7 +1,8 @@
-use git2::{BranchType, Commit, Cred, Oid, RemoteCallbacks, Repository};
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, Commit, Cred, Oid, RemoteCallbacks, Repository, StatusOptions};
 use std::collections::{BTreeMap, BTreeSet};
 
-pub fn remote_branches(repo: &Repository) -> git2::Branches {
+pub fn remote_branches(repo: &Repository) -> git2::Branches<'_> {
     repo.branches(Some(BranchType::Remote))
         .expect("remote branches working")
 }
@@ -24,7 +25,7 @@ pub fn remote_resolve_branch<'a>(
     branch_name: &str,
 ) -> Result<Commit<'a>, &'static str> {
     let to_find = format!("{}/{}", remote_name, branch_name);
-    let branch = remote_branches_get_name(&repo, &to_find).ok_or("remote branch not found")?;
+    let branch = remote_branches_get_name(repo, &to_find).ok_or("remote branch not found")?;
     let branch_ref = branch.into_reference();
     let branch_commit = branch_ref
         .peel_to_commit()
@@ -32,15 +33,13 @@ pub fn remote_resolve_branch<'a>(
     Ok(branch_commit)
 }
 
-pub fn all_tags<'a>(repo: &'a Repository) -> BTreeMap<String, Oid> {
+pub fn all_tags(repo: &Repository) -> BTreeMap<String, Oid> {
     let mut out = BTreeMap::new();
     repo.tag_foreach(|oid, name| {
         match std::str::from_utf8(name) {
             Ok(s) => {
                 if let Some(tagname) = s.strip_prefix("refs/tags/") {
                     out.insert(tagname.to_string(), oid);
-                } else {
-                    ()
                 }
             }
             Err(_) => {
@@ -65,21 +64,21 @@ pub fn has_remote_branch(repo: &Repository, remote_name: &str, branch: &str) ->
 
     let to_find = format!("{}/{}", remote_name, branch);
 
-    known_remote_branches.get(&to_find).is_some()
+    known_remote_branches.contains(&to_find)
 }
 
 pub fn has_remote_tag(repo: &Repository, tag: &str) -> bool {
     let tags = repo.tag_names(None).expect("tags working");
     let known_tags = tags
         .iter()
-        .filter_map(|v| v)
+        .flatten()
         .map(|x| x.to_string())
         .collect::<BTreeSet<_>>();
 
-    known_tags.get(tag).is_some()
+    known_tags.contains(tag)
 }
 
-pub fn remote_callbacks() -> RemoteCallbacks<'static> {
+pub fn remote_callbacks<'a>() -> RemoteCallbacks<'a> {
     let mut callbacks = RemoteCallbacks::new();
 
     // hardcoded to id_ed25519 file
@@ -96,3 +95,289 @@ pub fn remote_callbacks() -> RemoteCallbacks<'static> {
     });
     callbacks
 }
+
+/// Check if the working directory has any change, including untracked files
+pub fn is_dirty(repo: &Repository) -> bool {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut options)).expect("statuses working");
+    !statuses.is_empty()
+}
+
+/// Check if any local branch has commits that are not reachable from a remote branch
+pub fn has_unpushed_commits(repo: &Repository) -> bool {
+    let mut revwalk = repo.revwalk().expect("revwalk working");
+    revwalk
+        .push_glob("refs/heads/*")
+        .expect("push local branches");
+    revwalk
+        .hide_glob("refs/remotes/*")
+        .expect("hide remote branches");
+    revwalk.next().is_some()
+}
+
+/// Get the name of the checked out local branch, if any
+pub fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(|s| s.to_string())
+}
+
+/// Checkout an existing local branch, updating the working directory
+pub fn checkout_branch(repo: &Repository, branch: &str, force: bool) -> Result<(), git2::Error> {
+    let refname = format!("refs/heads/{}", branch);
+    let target = repo.revparse_single(&refname)?;
+
+    let mut checkout = CheckoutBuilder::new();
+    if force {
+        checkout.force();
+    } else {
+        checkout.safe();
+    }
+    repo.checkout_tree(&target, Some(&mut checkout))?;
+    repo.set_head(&refname)
+}
+
+/// State of a local branch compared to its upstream branch
+pub struct UpstreamState {
+    pub upstream_name: String,
+    pub upstream: Oid,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// Compare a local branch to its upstream branch, if it has one
+pub fn upstream_state(repo: &Repository, branch: &str) -> Option<UpstreamState> {
+    let local_branch = repo.find_branch(branch, BranchType::Local).ok()?;
+    let upstream_branch = local_branch.upstream().ok()?;
+    let upstream_name = upstream_branch.name().ok()??.to_string();
+    let local = local_branch.get().target()?;
+    let upstream = upstream_branch.get().target()?;
+    let (ahead, behind) = repo.graph_ahead_behind(local, upstream).ok()?;
+    Some(UpstreamState {
+        upstream_name,
+        upstream,
+        ahead,
+        behind,
+    })
+}
+
+/// Get all the local branches having an upstream branch
+pub fn tracking_branches(repo: &Repository) -> Vec<String> {
+    repo.branches(Some(BranchType::Local))
+        .expect("local branches working")
+        .filter_map(|x| x.ok().map(|x| x.0))
+        .filter(|b| b.upstream().is_ok())
+        .filter_map(|b| b.name().ok().flatten().map(|x| x.to_string()))
+        .collect()
+}
+
+/// Rebase the checked out branch on top of its upstream, aborting on conflicts
+pub fn rebase_on_upstream(repo: &Repository, branch: &str) -> Result<(), git2::Error> {
+    let local_branch = repo.find_branch(branch, BranchType::Local)?;
+    let upstream_branch = local_branch.upstream()?;
+    let local = repo.reference_to_annotated_commit(local_branch.get())?;
+    let upstream = repo.reference_to_annotated_commit(upstream_branch.get())?;
+    let signature = repo.signature()?;
+
+    let mut rebase = repo.rebase(Some(&local), Some(&upstream), None, None)?;
+    while let Some(operation) = rebase.next() {
+        let operation = operation.and_then(|_| {
+            if repo.index()?.has_conflicts() {
+                return Err(git2::Error::from_str("conflicts during rebase"));
+            }
+            match rebase.commit(None, &signature, None) {
+                // the patch is already applied upstream
+                Err(e) if e.code() == git2::ErrorCode::Applied => Ok(()),
+                r => r.map(|_| ()),
+            }
+        });
+        if let Err(e) = operation {
+            rebase.abort()?;
+            return Err(e);
+        }
+    }
+    rebase.finish(Some(&signature))
+}
+
+/// Move a local branch to a descendant commit, updating the working
+/// directory if the branch is checked out
+pub fn fast_forward(
+    repo: &Repository,
+    branch: &str,
+    target: Oid,
+    checkout: bool,
+) -> Result<(), git2::Error> {
+    if checkout {
+        let object = repo.find_object(target, None)?;
+        repo.checkout_tree(&object, Some(CheckoutBuilder::new().safe()))?;
+    }
+    let mut reference = repo.find_reference(&format!("refs/heads/{}", branch))?;
+    let _ = reference.set_target(target, "mugit: fast-forward")?;
+    Ok(())
+}
+
+/// Check if the repository is a partial clone, which libgit2 doesn't support
+pub fn is_partial_clone(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_string("extensions.partialclone"))
+        .is_ok()
+}
+
+/// Get the default branch of a remote repository without cloning it
+pub fn remote_default_branch(url: &str) -> Result<String, git2::Error> {
+    let mut remote = git2::Remote::create_detached(url)?;
+    let connection = remote.connect_auth(git2::Direction::Fetch, Some(remote_callbacks()), None)?;
+    let default_branch = connection.default_branch()?;
+    let default_branch = default_branch
+        .as_str()
+        .ok_or_else(|| git2::Error::from_str("default branch is not utf8"))?;
+    Ok(default_branch
+        .strip_prefix("refs/heads/")
+        .unwrap_or(default_branch)
+        .to_string())
+}
+
+/// Get the references advertised by a remote, with the commit they point to.
+///
+/// libgit2's local transport can't list the references of an empty
+/// repository, so the references of a local remote are read from the
+/// repository itself.
+pub fn remote_heads(remote: &mut git2::Remote) -> Result<BTreeMap<String, Oid>, git2::Error> {
+    let url = remote
+        .pushurl()
+        .or_else(|| remote.url())
+        .unwrap_or_default()
+        .to_string();
+    let path = std::path::Path::new(url.strip_prefix("file://").unwrap_or(&url));
+    if path.is_dir() {
+        let repo = Repository::open(path)?;
+        let mut heads = BTreeMap::new();
+        for reference in repo.references()? {
+            let reference = reference?;
+            if let (Some(name), Some(oid)) = (reference.name(), reference.target()) {
+                heads.insert(name.to_string(), oid);
+            }
+        }
+        return Ok(heads);
+    }
+    let connection = remote.connect_auth(git2::Direction::Push, Some(remote_callbacks()), None)?;
+    Ok(connection
+        .list()?
+        .iter()
+        .filter(|head| !head.oid().is_zero())
+        .map(|head| (head.name().to_string(), head.oid()))
+        .collect())
+}
+
+/// Get the time of the last fetch, or of the clone if never fetched since
+pub fn last_fetch_time(repo: &Repository) -> Option<std::time::SystemTime> {
+    ["FETCH_HEAD", "packed-refs"]
+        .iter()
+        .filter_map(|f| std::fs::metadata(repo.path().join(f)).ok())
+        .find_map(|m| m.modified().ok())
+}
+
+/// A push refspec with the wildcards expanded
+pub struct PushRef {
+    pub force: bool,
+    pub src: String,
+    pub dst: String,
+}
+
+/// Expand the wildcards of push refspecs (`[+]<src>:<dst>`) over the local
+/// references, skipping the symbolic ones like `refs/remotes/origin/HEAD`
+pub fn expand_push_refspecs(
+    repo: &Repository,
+    refspecs: &[String],
+) -> Result<Vec<PushRef>, git2::Error> {
+    let mut out = Vec::new();
+    for refspec in refspecs {
+        let (force, spec) = match refspec.strip_prefix('+') {
+            Some(spec) => (true, spec),
+            None => (false, refspec.as_str()),
+        };
+        let (src, dst) = spec.split_once(':').unwrap_or((spec, spec));
+        match src.split_once('*') {
+            None => out.push(PushRef {
+                force,
+                src: src.to_string(),
+                dst: dst.to_string(),
+            }),
+            Some((src_prefix, src_suffix)) => {
+                for reference in repo.references_glob(src)? {
+                    let reference = reference?;
+                    if reference.kind() != Some(git2::ReferenceType::Direct) {
+                        continue;
+                    }
+                    let name = match reference.name() {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let matched = &name[src_prefix.len()..name.len() - src_suffix.len()];
+                    out.push(PushRef {
+                        force,
+                        src: name.to_string(),
+                        dst: dst.replacen('*', matched, 1),
+                    })
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_push_refspecs_wildcards() {
+        let dir = std::env::temp_dir().join(format!("mugit-refspecs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = Repository::init(&dir).unwrap();
+        let sig = git2::Signature::now("mugit", "mugit@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let oid = repo
+            .commit(None, &sig, &sig, "initial", &tree, &[])
+            .unwrap();
+        for name in [
+            "refs/heads/main",
+            "refs/heads/feature",
+            "refs/remotes/origin/main",
+        ] {
+            repo.reference(name, oid, true, "test").unwrap();
+        }
+        // symbolic references are not pushed
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            true,
+            "test",
+        )
+        .unwrap();
+
+        let refspecs = [
+            "+refs/remotes/origin/*:refs/heads/*".to_string(),
+            "refs/heads/*:refs/heads/local/*".to_string(),
+            "refs/heads/main".to_string(),
+        ];
+        let refs = expand_push_refspecs(&repo, &refspecs).unwrap();
+        let refs = refs
+            .iter()
+            .map(|r| (r.force, r.src.as_str(), r.dst.as_str()))
+            .collect::<BTreeSet<_>>();
+        let expected = [
+            (true, "refs/remotes/origin/main", "refs/heads/main"),
+            (false, "refs/heads/feature", "refs/heads/local/feature"),
+            (false, "refs/heads/main", "refs/heads/local/main"),
+            (false, "refs/heads/main", "refs/heads/main"),
+        ];
+        assert_eq!(refs, BTreeSet::from(expected));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use git2::Repository;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+
+use super::cargo;
+use super::manifest::Project;
+use super::util;
+
+/// Why a repository depends on another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DepKind {
+    /// declared in the manifest with `depends-on`
+    Manifest,
+    /// cargo dependency by git url
+    CargoGit,
+    /// cargo dependency by path
+    CargoPath,
+    Submodule,
+}
+
+impl std::fmt::Display for DepKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DepKind::Manifest => "depends-on",
+            DepKind::CargoGit => "cargo git",
+            DepKind::CargoPath => "cargo path",
+            DepKind::Submodule => "submodule",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Dependencies between the repositories of a project, by checkout directory
+pub struct DepGraph {
+    /// repositories in manifest order
+    pub nodes: Vec<String>,
+    /// dependencies of each repository
+    pub edges: BTreeMap<String, BTreeSet<(String, DepKind)>>,
+}
+
+/// Normalize a git url to compare urls written differently
+fn normalize_url(url: &str) -> &str {
+    let url = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url)
+}
+
+/// Resolve the `.` and `..` of a path without touching the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Get the git urls and the paths of the cargo dependencies of a crate or a workspace
+fn cargo_dependencies(manifest: &toml::Value) -> (Vec<String>, Vec<String>) {
+    let mut tables = Vec::new();
+    for key in cargo::DEPENDENCY_TABLES.iter() {
+        tables.extend(manifest.get(key));
+        tables.extend(manifest.get("workspace").and_then(|w| w.get(key)));
+    }
+    if let Some(targets) = manifest.get("target").and_then(|t| t.as_table()) {
+        for target in targets.values() {
+            for key in cargo::DEPENDENCY_TABLES.iter() {
+                tables.extend(target.get(key));
+            }
+        }
+    }
+
+    let mut git = Vec::new();
+    let mut paths = Vec::new();
+    for dep in tables
+        .into_iter()
+        .filter_map(|t| t.as_table())
+        .flat_map(|t| t.values())
+    {
+        if let Some(url) = dep.get("git").and_then(|v| v.as_str()) {
+            git.push(url.to_string())
+        }
+        if let Some(path) = dep.get("path").and_then(|v| v.as_str()) {
+            paths.push(path.to_string())
+        }
+    }
+    (git, paths)
+}
+
+impl DepGraph {
+    /// Build the graph from the manifest and from the cargo dependencies and
+    /// submodules of the synced repositories pointing at other repositories
+    pub fn build(project: &Project, out_dir: &Path) -> anyhow::Result<DepGraph> {
+        let destinations = project.destinations()?;
+        let nodes = destinations
+            .iter()
+            .map(|(_, dest)| dest.to_str().expect("git with valid UTF8").to_string())
+            .collect::<Vec<_>>();
+        let urls = destinations
+            .iter()
+            .zip(nodes.iter())
+            .map(|((repo, _), name)| (normalize_url(&repo.url(project)).to_string(), name))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut edges: BTreeMap<String, BTreeSet<(String, DepKind)>> = BTreeMap::new();
+        for ((repo_entry, dest), name) in destinations.iter().zip(nodes.iter()) {
+            let deps = edges.entry(name.clone()).or_default();
+            for dep in repo_entry.depends_on.iter() {
+                let target = destinations
+                    .iter()
+                    .zip(nodes.iter())
+                    .find(|((r, _), n)| &r.path == dep || *n == dep)
+                    .map(|(_, n)| n)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("repository {} depends on unknown {}", name, dep)
+                    })?;
+                deps.insert((target.clone(), DepKind::Manifest));
+            }
+
+            let repo_dir = out_dir.join(dest);
+            for path in cargo::workspace_manifests(&repo_dir) {
+                let manifest = match cargo::read_manifest(&path) {
+                    Some(manifest) => manifest,
+                    None => continue,
+                };
+                let dir = path.parent().expect("manifest has a parent");
+                let (git, paths) = cargo_dependencies(&manifest);
+                for url in git {
+                    if let Some(target) = urls.get(normalize_url(&url)) {
+                        deps.insert((target.to_string(), DepKind::CargoGit));
+                    }
+                }
+                for path in paths {
+                    let target = normalize_path(&dir.join(path));
+                    let found = destinations
+                        .iter()
+                        .zip(nodes.iter())
+                        .find(|((_, d), _)| target.starts_with(out_dir.join(d)));
+                    if let Some((_, target)) = found {
+                        deps.insert((target.clone(), DepKind::CargoPath));
+                    }
+                }
+            }
+
+            if let Ok(repo) = Repository::open(&repo_dir) {
+                let url = repo_entry.url(project);
+                for submodule in repo.submodules().unwrap_or_default() {
+                    if let Some(sub_url) = submodule.url() {
+                        let sub_url = util::resolve_relative_url(&url, sub_url);
+                        if let Some(target) = urls.get(normalize_url(&sub_url)) {
+                            deps.insert((target.to_string(), DepKind::Submodule));
+                        }
+                    }
+                }
+            }
+            deps.retain(|(target, _)| target != name);
+        }
+        Ok(DepGraph { nodes, edges })
+    }
+
+    /// Get the repositories with every one after its dependencies, otherwise in manifest order
+    pub fn topo_order(&self) -> anyhow::Result<Vec<String>> {
+        let mut out: Vec<String> = Vec::new();
+        let mut done = BTreeSet::new();
+        while out.len() < self.nodes.len() {
+            let next = self.nodes.iter().find(|node| {
+                !done.contains(*node) && self.edges[*node].iter().all(|(dep, _)| done.contains(dep))
+            });
+            match next {
+                Some(node) => {
+                    done.insert(node.clone());
+                    out.push(node.clone())
+                }
+                None => {
+                    let cycle = self
+                        .nodes
+                        .iter()
+                        .filter(|node| !done.contains(*node))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    anyhow::bail!("dependency cycle between {}", cycle.join(", "))
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph mugit {\n");
+        for node in self.nodes.iter() {
+            out.push_str(&format!("  \"{}\";\n", node));
+        }
+        for node in self.nodes.iter() {
+            for (dep, kind) in self.edges[node].iter() {
+                out.push_str(&format!(
+                    "  \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                    node, dep, kind
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_with(edges: &[(&str, &str)]) -> DepGraph {
+        let nodes = ["app", "core", "utils", "tools"];
+        let mut graph = DepGraph {
+            nodes: nodes.iter().map(|n| n.to_string()).collect(),
+            edges: nodes
+                .iter()
+                .map(|n| (n.to_string(), BTreeSet::new()))
+                .collect(),
+        };
+        for (from, to) in edges {
+            graph
+                .edges
+                .get_mut(*from)
+                .unwrap()
+                .insert((to.to_string(), DepKind::Manifest));
+        }
+        graph
+    }
+
+    #[test]
+    fn topo_order_after_dependencies() {
+        let graph = graph_with(&[("app", "core"), ("core", "utils"), ("app", "utils")]);
+        assert_eq!(
+            graph.topo_order().unwrap(),
+            ["utils", "core", "app", "tools"]
+        );
+        // without dependencies the manifest order is kept
+        assert_eq!(
+            graph_with(&[]).topo_order().unwrap(),
+            ["app", "core", "utils", "tools"]
+        );
+    }
+
+    #[test]
+    fn topo_order_cycle() {
+        let graph = graph_with(&[("app", "core"), ("core", "utils"), ("utils", "app")]);
+        let err = graph.topo_order().unwrap_err().to_string();
+        assert_eq!(err, "dependency cycle between app, core, utils");
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            normalize_path(Path::new("/out/core/./../utils/src/..")),
+            Path::new("/out/utils")
+        );
+        assert_eq!(
+            normalize_url("https://host/org/core.git/"),
+            "https://host/org/core"
+        );
+    }
+}
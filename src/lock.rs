@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Name of the lock manifest written in the generated archives
+pub const LOCK_FILE_NAME: &str = "mugit.lock";
+
+/// Commit of every repository of a project, as resolved from a revision
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lock {
+    /// revision the commits were resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// repositories by checkout directory
+    #[serde(default)]
+    pub repos: BTreeMap<String, LockedRepo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedRepo {
+    pub url: String,
+    pub commit: String,
+}
+
+impl Lock {
+    /// Read a lock manifest, checking that every commit is a full commit id
+    pub fn from_file<P: AsRef<Path>>(file: P) -> anyhow::Result<Lock> {
+        let file = file.as_ref();
+        let content = std::fs::read_to_string(file)?;
+        Self::from_toml(&content).map_err(|e| anyhow::anyhow!("{:?}: {}", file, e))
+    }
+
+    fn from_toml(content: &str) -> anyhow::Result<Lock> {
+        let lock: Lock = toml::from_str(content)?;
+        for (name, locked) in lock.repos.iter() {
+            let full =
+                locked.commit.len() == 40 && locked.commit.bytes().all(|b| b.is_ascii_hexdigit());
+            if !full {
+                anyhow::bail!(
+                    "repository {} has an invalid commit {:?}",
+                    name,
+                    locked.commit
+                )
+            }
+        }
+        Ok(lock)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("lock serialization")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut lock = Lock {
+            rev: Some("v1.0".to_string()),
+            ..Lock::default()
+        };
+        lock.repos.insert(
+            "libs/utils".to_string(),
+            LockedRepo {
+                url: "https://host/utils.git".to_string(),
+                commit: "0123456789abcdef0123456789abcdef01234567".to_string(),
+            },
+        );
+        let loaded = Lock::from_toml(&lock.to_toml()).unwrap();
+        assert_eq!(loaded.rev.as_deref(), Some("v1.0"));
+        assert_eq!(loaded.repos["libs/utils"].url, "https://host/utils.git");
+    }
+
+    #[test]
+    fn short_commits_rejected() {
+        for commit in ["0123456", "", "0123456789abcdef0123456789abcdef0123456g"] {
+            let content = format!(
+                "[repos.core]\nurl = \"/srv/core\"\ncommit = \"{}\"\n",
+                commit
+            );
+            let err = Lock::from_toml(&content).unwrap_err().to_string();
+            assert!(
+                err.starts_with("repository core has an invalid commit"),
+                "{}",
+                err
+            );
+        }
+    }
+}
@@ -1,20 +1,34 @@
 //use anyhow::anyhow;
 use clap::{App, Arg};
 
+mod archive;
+mod bundle;
+mod cache;
+mod cargo;
 mod commands;
+mod completion;
 mod githelp;
+mod graph;
+mod lock;
 mod manifest;
+mod manifest_repos;
 mod project;
+mod topic;
+mod tui;
 mod util;
 mod ver;
 
 use commands::*;
 
-fn init() -> Result<Option<manifest::Manifests>, std::io::Error> {
+fn mugit_dir() -> std::path::PathBuf {
     #[allow(deprecated)]
     let home_dir = std::env::home_dir().expect("HOME is defined");
 
-    let mugit_dir = home_dir.join(".mugit");
+    home_dir.join(".mugit")
+}
+
+fn init() -> Result<Option<manifest::Manifests>, std::io::Error> {
+    let mugit_dir = mugit_dir();
     if !mugit_dir.is_dir() {
         return Ok(None);
     }
@@ -23,6 +37,7 @@ fn init() -> Result<Option<manifest::Manifests>, std::io::Error> {
 
 fn main() {
     const ARG_GIT_EXEC: &str = "git-exec";
+    const ARG_OFFLINE: &str = "offline";
     const ARG_INIT_SKIP_LOAD: &str = "init-skip-load";
     const ARG_REPO: &str = "repo";
     const ARG_SPEC: &str = "spec";
@@ -36,12 +51,67 @@ fn main() {
     const ARG_SHOW_NO_DIFF: &str = "show-no-diff";
     const ARG_SKIP_PUSH: &str = "skip-push";
     const ARG_REMOTE_NAME: &str = "remote-name";
+    const ARG_REMOTE: &str = "remote";
     const ARG_REV1: &str = "rev1";
     const ARG_REV2: &str = "rev2";
     const ARG_MANIFEST_FILE: &str = "manifest-file";
     const ARG_MANIFEST_DEST: &str = "manifest-dest";
+    const ARG_CREATE: &str = "create";
+    const ARG_FORCE: &str = "force";
+    const ARG_UPDATE_BRANCHES: &str = "update-branches";
+    const ARG_ALL_BRANCHES: &str = "all-branches";
+    const ARG_REBASE: &str = "rebase";
+    const ARG_DEPTH: &str = "depth";
+    const ARG_SINGLE_BRANCH: &str = "single-branch";
+    const ARG_FILTER: &str = "filter";
+    const ARG_UNSHALLOW: &str = "unshallow";
+    const ARG_CACHE: &str = "cache";
+    const ARG_CACHE_DIR: &str = "cache-dir";
+    const ARG_MAX_AGE: &str = "max-age";
+    const ARG_DRY_RUN: &str = "dry-run";
+    const ARG_PRUNE: &str = "prune";
+    const ARG_PRUNE_DRY_RUN: &str = "prune-dry-run";
+    const ARG_PRUNE_ARCHIVE: &str = "prune-archive";
+    const ARG_REV: &str = "rev";
+    const ARG_OUTPUT: &str = "output";
+    const ARG_SUBMODULES: &str = "submodules";
+    const ARG_SINCE: &str = "since";
+    const ARG_BUNDLE_DIR: &str = "bundle-dir";
+    const ARG_TO: &str = "to";
+    const ARG_REFSPEC: &str = "refspec";
+    const ARG_PATTERN: &str = "pattern";
+    const ARG_IGNORE_CASE: &str = "ignore-case";
+    const ARG_FIXED_STRINGS: &str = "fixed-strings";
+    const ARG_PATH: &str = "path";
+    const ARG_JOBS: &str = "jobs";
+    const ARG_RANGE: &str = "range";
+    const ARG_GREP: &str = "grep";
+    const ARG_AUTHOR: &str = "author";
+    const ARG_SINCE_DATE: &str = "since-date";
+    const ARG_UNTIL_DATE: &str = "until-date";
+    const ARG_MAX_COUNT: &str = "max-count";
+    const ARG_TOPIC: &str = "topic";
+    const ARG_REPOS: &str = "repos";
+    const ARG_FROM: &str = "from";
+    const ARG_MESSAGE: &str = "message";
+    const ARG_ALL: &str = "all";
+    const ARG_INCLUDE_UNTRACKED: &str = "include-untracked";
+    const ARG_FORMAT: &str = "format";
+    const ARG_TOPO: &str = "topo";
+    const ARG_COMMAND: &str = "command";
+    const ARG_CHECK_CARGO_VERSION: &str = "check-cargo-version";
+    const ARG_CRATE: &str = "crate";
+    const ARG_VERSION: &str = "version";
+    const ARG_COMMIT_MESSAGE: &str = "commit-message";
+    const ARG_SIDE_A: &str = "a";
+    const ARG_SIDE_B: &str = "b";
+    const ARG_SHELL: &str = "shell";
+    const ARG_URL: &str = "url";
+    const ARG_NAME: &str = "name";
 
     const SUBCMD_VERSION_FIND: &str = "version-find";
+    const SUBCMD_CACHE: &str = "cache";
+    const SUBCMD_CACHE_GC: &str = "gc";
     const SUBCMD_HAS_BRANCH: &str = "has-branch";
     const SUBCMD_MANIFEST_SYNC: &str = "manifest-sync";
     const SUBCMD_MANIFEST_DEBUG: &str = "manifest-debug";
@@ -49,8 +119,37 @@ fn main() {
     const SUBCMD_MANIFEST_HAS_TAG: &str = "manifest-has-tag";
     const SUBCMD_MANIFEST_SET_BRANCH: &str = "manifest-set-branch";
     const SUBCMD_MANIFEST_SET_TAG: &str = "manifest-set-tag";
+    const SUBCMD_MANIFEST_CHECKOUT: &str = "manifest-checkout";
     const SUBCMD_MANIFEST_HAS_CHANGE: &str = "manifest-has-change";
     const SUBCMD_MANIFEST_CHANGELOG: &str = "manifest-changelog";
+    const SUBCMD_MANIFEST_ARCHIVE: &str = "manifest-archive";
+    const SUBCMD_MANIFEST_BUNDLE: &str = "manifest-bundle";
+    const SUBCMD_MANIFEST_MIRROR: &str = "manifest-mirror";
+    const SUBCMD_MANIFEST_GREP: &str = "manifest-grep";
+    const SUBCMD_MANIFEST_LOG: &str = "manifest-log";
+    const SUBCMD_MANIFEST_START: &str = "manifest-start";
+    const SUBCMD_MANIFEST_PUSH_TOPIC: &str = "manifest-push-topic";
+    const SUBCMD_MANIFEST_FINISH: &str = "manifest-finish";
+    const SUBCMD_MANIFEST_STATUS: &str = "manifest-status";
+    const SUBCMD_MANIFEST_COMMIT: &str = "manifest-commit";
+    const SUBCMD_MANIFEST_STASH: &str = "manifest-stash";
+    const SUBCMD_MANIFEST_GRAPH: &str = "manifest-graph";
+    const SUBCMD_MANIFEST_FOREACH: &str = "manifest-foreach";
+    const SUBCMD_MANIFEST_CHECK_VERSIONS: &str = "manifest-check-versions";
+    const SUBCMD_MANIFEST_BUMP_DEP: &str = "manifest-bump-dep";
+    const SUBCMD_MANIFEST_DIFF: &str = "manifest-diff";
+    const SUBCMD_TUI: &str = "tui";
+    const SUBCMD_COMPLETIONS: &str = "completions";
+    const SUBCMD_MAN: &str = "man";
+    const SUBCMD_COMPLETE_PROJECTS: &str = completion::COMPLETE_PROJECTS;
+    const SUBCMD_MANIFEST_REPO: &str = "manifest-repo";
+    const SUBCMD_MANIFEST_REPO_ADD: &str = "add";
+    const SUBCMD_MANIFEST_REPO_UPDATE: &str = "update";
+    const SUBCMD_STASH_PUSH: &str = "push";
+    const SUBCMD_STASH_POP: &str = "pop";
+    const SUBCMD_STASH_LIST: &str = "list";
+    const SUBCMD_BUNDLE_CREATE: &str = "create";
+    const SUBCMD_BUNDLE_APPLY: &str = "apply";
 
     let arg_repo = Arg::new(ARG_REPO)
         .short('r')
@@ -79,11 +178,30 @@ fn main() {
         .value_name("OUTPUT-DIR")
         .required(false);
 
+    let arg_remote = Arg::new(ARG_REMOTE)
+        .long("remote")
+        .value_name("REMOTE")
+        .help("use this remote instead of the repository main remote")
+        .takes_value(true)
+        .required(false);
+
+    let arg_cache_dir = Arg::new(ARG_CACHE_DIR)
+        .long("cache-dir")
+        .value_name("CACHE-DIR")
+        .help("directory of the mirrors, by default ~/.mugit/cache")
+        .takes_value(true)
+        .required(false);
+
     let arg_continue_on_fail = Arg::new(ARG_CONTINUE_ON_FAIL)
         .long("continue-on-fail")
         .help("continue if something fails")
         .takes_value(false);
 
+    let arg_topo = Arg::new(ARG_TOPO)
+        .long("topo")
+        .help("go through the repositories after their dependencies, see manifest-graph")
+        .takes_value(false);
+
     let arg_continue_if_exists = Arg::new(ARG_CONTINUE_IF_EXISTS)
         .long("continue-if-exists")
         .help("continue if tag already exists")
@@ -109,6 +227,14 @@ fn main() {
             .multiple(false)
     };
 
+    let arg_topic = |s| {
+        Arg::new(ARG_TOPIC)
+            .help(s)
+            .takes_value(true)
+            .required(true)
+            .multiple(false)
+    };
+
     let arg_commit = |s| {
         Arg::new(ARG_COMMIT)
             .help(s)
@@ -127,6 +253,12 @@ fn main() {
                 .long("git-exec")
                 .takes_value(false),
         )
+        .arg(
+            Arg::new(ARG_OFFLINE)
+                .help("don't access the network, using only the local state of the repositories")
+                .long("offline")
+                .takes_value(false),
+        )
         .arg(
             Arg::new(ARG_INIT_SKIP_LOAD)
                 .long("skip-init-load")
@@ -155,12 +287,36 @@ fn main() {
                         .multiple(false),
                 ),
         )
+        .subcommand(
+            App::new(SUBCMD_CACHE)
+                .about("manage the local cache of mirrors")
+                .subcommand(
+                    App::new(SUBCMD_CACHE_GC)
+                        .about("remove the mirrors that have not been used recently")
+                        .arg(&arg_cache_dir)
+                        .arg(
+                            Arg::new(ARG_MAX_AGE)
+                                .long("max-age")
+                                .value_name("DAYS")
+                                .help("remove the mirrors unused for this number of days")
+                                .default_value("30")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new(ARG_DRY_RUN)
+                                .long("dry-run")
+                                .help("only list the mirrors that would be removed")
+                                .takes_value(false),
+                        ),
+                ),
+        )
         .subcommand(App::new(SUBCMD_MANIFEST_DEBUG).arg(&arg_manifest_file))
         .subcommand(
             App::new(SUBCMD_MANIFEST_HAS_BRANCH)
                 .arg(&arg_project)
                 .arg(&arg_manifest_file)
                 .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
                 /*
                 .arg(
                     Arg::new(ARG_REMOTE_NAME)
@@ -185,6 +341,7 @@ fn main() {
                 .arg(&arg_project)
                 .arg(&arg_manifest_file)
                 .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
                 .arg(&arg_continue_if_exists)
                 .arg(arg_branch("specify which branch to set to the project"))
                 .arg(arg_commit("specify which commit"))
@@ -202,7 +359,15 @@ fn main() {
                 .arg(&arg_project)
                 .arg(&arg_manifest_file)
                 .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
                 .arg(&arg_continue_if_exists)
+                .arg(&arg_topo)
+                .arg(
+                    Arg::new(ARG_CHECK_CARGO_VERSION)
+                        .long("check-cargo-version")
+                        .help("check first that the Cargo.toml versions match the tag")
+                        .takes_value(false),
+                )
                 .arg(arg_tag("specify which tag to set to the project"))
                 .arg(arg_branch("specify which branch the tag apply to"))
                 .arg(
@@ -213,12 +378,57 @@ fn main() {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_CHECK_VERSIONS)
+                .about("check that the Cargo.toml versions of the branch match a release tag")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(arg_tag("release tag to compare the versions with"))
+                .arg(arg_branch("branch to read the Cargo.toml from"))
+                .arg(
+                    Arg::new(ARG_OR_BRANCH)
+                        .help("set a backup branch if branch is not found")
+                        .long("or-branch")
+                        .required(false)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_CHECKOUT)
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(arg_branch("specify which branch to checkout"))
+                .arg(
+                    Arg::new(ARG_OR_BRANCH)
+                        .help("set a backup branch if branch is not found")
+                        .long("or-branch")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_CREATE)
+                        .long("create")
+                        .help("create the branch from the backup branch if not found")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_FORCE)
+                        .long("force")
+                        .help("checkout even if the repository has local changes, discarding them")
+                        .takes_value(false),
+                ),
+        )
         .subcommand(
             App::new(SUBCMD_MANIFEST_HAS_CHANGE)
                 .arg(&arg_project)
                 .arg(&arg_continue_on_fail)
                 .arg(&arg_manifest_file)
                 .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
                 .arg(arg_tag("specify which reference to use"))
                 .arg(arg_branch("specify which branch to compare reference to")),
         )
@@ -228,6 +438,7 @@ fn main() {
                 .arg(&arg_continue_on_fail)
                 .arg(&arg_manifest_file)
                 .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
                 .arg(
                     Arg::new(ARG_REV1)
                         .help("old revision to start")
@@ -251,29 +462,602 @@ fn main() {
                 ),
         )
         .subcommand(
-            App::new(SUBCMD_MANIFEST_SYNC)
+            App::new(SUBCMD_MANIFEST_ARCHIVE)
+                .about("write every repository at a revision into a tar or zip archive")
+                .arg(&arg_project)
+                .arg(&arg_continue_on_fail)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(
+                    Arg::new(ARG_REV)
+                        .help("tag or branch to archive")
+                        .required(true)
+                        .takes_value(true)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::new(ARG_OUTPUT)
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("archive to write, as .tar, .tar.gz, .tgz or .zip")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_SUBMODULES)
+                        .long("submodules")
+                        .help("include the content of the submodules")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_BUNDLE)
+                .about("move the repositories without network access, using git bundles")
+                .subcommand(
+                    App::new(SUBCMD_BUNDLE_CREATE)
+                        .about("write a bundle of every repository and an index into a directory")
+                        .arg(&arg_project)
+                        .arg(&arg_continue_on_fail)
+                        .arg(&arg_manifest_file)
+                        .arg(&arg_manifest_dest)
+                        .arg(&arg_remote)
+                        .arg(
+                            Arg::new(ARG_BUNDLE_DIR)
+                                .short('o')
+                                .long("output")
+                                .value_name("BUNDLE-DIR")
+                                .help("directory to write the bundles to")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new(ARG_SINCE)
+                                .long("since")
+                                .value_name("TAG-OR-LOCKFILE")
+                                .help("only bundle the history since a tag or a lockfile")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    App::new(SUBCMD_BUNDLE_APPLY)
+                        .about("fetch from the bundles of a directory, cloning the missing repositories")
+                        .arg(&arg_project)
+                        .arg(&arg_continue_on_fail)
+                        .arg(&arg_manifest_file)
+                        .arg(&arg_manifest_dest)
+                        .arg(
+                            Arg::new(ARG_BUNDLE_DIR)
+                                .help("directory of the bundles and their index")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_MIRROR)
+                .about("push the branches and tags of every repository to another server")
+                .arg(&arg_project)
+                .arg(&arg_continue_on_fail)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(
+                    Arg::new(ARG_TO)
+                        .long("to")
+                        .value_name("PREFIX")
+                        .help("prefix of the mirror urls, followed by the repository path")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_REFSPEC)
+                        .long("refspec")
+                        .value_name("REFSPEC")
+                        .help("refspec to push instead of the remote branches and tags, can be repeated")
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_GREP)
+                .about("search a regular expression in every repository")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(
+                    Arg::new(ARG_PATTERN)
+                        .help("regular expression to search")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_REV)
+                        .long("rev")
+                        .value_name("REV")
+                        .help("search a tag, remote branch or commit instead of the working tree")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_IGNORE_CASE)
+                        .short('i')
+                        .long("ignore-case")
+                        .help("match case insensitively")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_FIXED_STRINGS)
+                        .short('F')
+                        .long("fixed-strings")
+                        .help("search the pattern literally instead of as a regular expression")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_PATH)
+                        .long("path")
+                        .value_name("GLOB")
+                        .help("only search the files matching this pattern or in this directory, can be repeated")
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_JOBS)
+                        .short('j')
+                        .long("jobs")
+                        .value_name("N")
+                        .help("number of repositories searched in parallel, by default the number of cpus")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_LOG)
+                .about("show the commits of every repository, merged and sorted by time")
+                .arg(&arg_project)
+                .arg(&arg_continue_on_fail)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(
+                    Arg::new(ARG_RANGE)
+                        .help("revisions as REV1..REV2, by default the whole history")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_GREP)
+                        .long("grep")
+                        .value_name("REGEX")
+                        .help("only the commits with a message matching this regular expression")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_AUTHOR)
+                        .long("author")
+                        .value_name("REGEX")
+                        .help("only the commits with an author name or email matching this regular expression")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_SINCE_DATE)
+                        .long("since")
+                        .value_name("YYYY-MM-DD")
+                        .help("only the commits authored on or after this date")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_UNTIL_DATE)
+                        .long("until")
+                        .value_name("YYYY-MM-DD")
+                        .help("only the commits authored on or before this date")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_PATH)
+                        .long("path")
+                        .value_name("PATHSPEC")
+                        .help("only the commits changing this path, can be repeated")
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_MAX_COUNT)
+                        .short('n')
+                        .long("max-count")
+                        .value_name("N")
+                        .help("show only the N most recent commits")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_START)
+                .about("create and checkout a topic branch in some repositories")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(arg_topic("name of the topic branch"))
+                .arg(
+                    Arg::new(ARG_REPOS)
+                        .long("repos")
+                        .value_name("REPO,...")
+                        .help("repositories of the topic, by checkout directory")
+                        .required(true)
+                        .use_value_delimiter(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_FROM)
+                        .long("from")
+                        .value_name("BRANCH")
+                        .help("remote branch to start from, by default the upstream of the checked out branch")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_PUSH_TOPIC)
+                .about("push the branch of a topic in all its repositories")
+                .arg(&arg_skip_push)
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(arg_topic("name of the topic branch")),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_FINISH)
+                .about("delete the branch of a topic where it's merged")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(arg_topic("name of the topic branch"))
+                .arg(
+                    Arg::new(ARG_FORCE)
+                        .long("force")
+                        .help("delete the branch even if not merged, discarding the local changes")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_STATUS)
+                .about("show the checked out branch and topics of every repository")
                 .arg(&arg_project)
                 .arg(&arg_manifest_file)
                 .arg(&arg_manifest_dest),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_COMMIT)
+                .about("commit in every repository with changes, with the same message")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(
+                    Arg::new(ARG_MESSAGE)
+                        .short('m')
+                        .long("message")
+                        .value_name("MESSAGE")
+                        .help("commit message, to which a Change-Set-Id trailer is added")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_ALL)
+                        .short('a')
+                        .long("all")
+                        .help("stage the changes of the tracked files first, as git commit -a")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_INCLUDE_UNTRACKED)
+                        .short('u')
+                        .long("include-untracked")
+                        .help("with --all, also stage the untracked files")
+                        .requires(ARG_ALL)
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_STASH)
+                .about("stash the local changes of every repository")
+                .subcommand(
+                    App::new(SUBCMD_STASH_PUSH)
+                        .about("stash the local changes of every repository")
+                        .arg(&arg_project)
+                        .arg(&arg_manifest_file)
+                        .arg(&arg_manifest_dest)
+                        .arg(
+                            Arg::new(ARG_MESSAGE)
+                                .short('m')
+                                .long("message")
+                                .value_name("MESSAGE")
+                                .help("description added to the stash message")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new(ARG_INCLUDE_UNTRACKED)
+                                .short('u')
+                                .long("include-untracked")
+                                .help("also stash the untracked files")
+                                .takes_value(false),
+                        ),
+                )
+                .subcommand(
+                    App::new(SUBCMD_STASH_POP)
+                        .about("pop the last stash created by mugit in every repository")
+                        .arg(&arg_project)
+                        .arg(&arg_manifest_file)
+                        .arg(&arg_manifest_dest),
+                )
+                .subcommand(
+                    App::new(SUBCMD_STASH_LIST)
+                        .about("list the stashes created by mugit in every repository")
+                        .arg(&arg_project)
+                        .arg(&arg_manifest_file)
+                        .arg(&arg_manifest_dest),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_REPO)
+                .about("manage the git repositories of manifests, selected as repo/manifest::project")
+                .subcommand(
+                    App::new(SUBCMD_MANIFEST_REPO_ADD)
+                        .about("clone a manifest repository into the mugit directory")
+                        .arg(
+                            Arg::new(ARG_URL)
+                                .value_name("URL")
+                                .help("url of the manifest repository")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new(ARG_NAME)
+                                .long("name")
+                                .value_name("NAME")
+                                .help("name of the repository in the selectors, by default the last component of the url")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    App::new(SUBCMD_MANIFEST_REPO_UPDATE)
+                        .about("fetch and fast-forward the manifest repositories")
+                        .arg(
+                            Arg::new(ARG_NAME)
+                                .value_name("NAME")
+                                .help("only update this manifest repository")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_COMPLETIONS)
+                .about("print the shell completion script, completing -p with the known manifests")
+                .arg(
+                    Arg::new(ARG_SHELL)
+                        .value_name("SHELL")
+                        .possible_values(["bash", "zsh", "fish"])
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(App::new(SUBCMD_MAN).about("print the man page"))
+        .subcommand(
+            App::new(SUBCMD_COMPLETE_PROJECTS)
+                .about("list the project selectors, for the shell completion")
+                .hide(true),
+        )
+        .subcommand(
+            App::new(SUBCMD_TUI)
+                .about("full screen dashboard of the repositories, to fetch, checkout and browse them")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_DIFF)
+                .about("compare every repository between two tags, branches or lockfiles")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_remote)
+                .arg(&arg_continue_on_fail)
+                .arg(
+                    Arg::new(ARG_SIDE_A)
+                        .value_name("A")
+                        .help("tag, branch or lockfile to compare from")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_SIDE_B)
+                        .value_name("B")
+                        .help("tag, branch or lockfile to compare to")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_BUMP_DEP)
+                .about("change the version requirement of a dependency in every Cargo.toml, Cargo.lock is left as is")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(
+                    Arg::new(ARG_CRATE)
+                        .value_name("CRATE")
+                        .help("name of the dependency")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_VERSION)
+                        .value_name("VERSION")
+                        .help("new version requirement, keeping any ^, ~ or = operator")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_DRY_RUN)
+                        .long("dry-run")
+                        .help("only show the requirements that would change")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_COMMIT_MESSAGE)
+                        .long("commit")
+                        .value_name("MESSAGE")
+                        .help("commit only the changed Cargo.toml files, with this message and a Change-Set-Id trailer")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_GRAPH)
+                .about("show the dependencies between the repositories")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(
+                    Arg::new(ARG_FORMAT)
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("text, or dot for graphviz")
+                        .possible_values(["text", "dot"])
+                        .default_value("text")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_FOREACH)
+                .about("run a command in every repository")
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(&arg_continue_on_fail)
+                .arg(&arg_topo)
+                .arg(
+                    Arg::new(ARG_COMMAND)
+                        .value_name("COMMAND")
+                        .help("command run in each repository with MUGIT_REPO set, by sh when given as a single argument")
+                        .required(true)
+                        .multiple_values(true)
+                        .last(true),
+                ),
+        )
+        .subcommand(
+            App::new(SUBCMD_MANIFEST_SYNC)
+                .arg(&arg_project)
+                .arg(&arg_manifest_file)
+                .arg(&arg_manifest_dest)
+                .arg(
+                    Arg::new(ARG_DEPTH)
+                        .long("depth")
+                        .value_name("DEPTH")
+                        .help("clone only this number of commits of history")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_SINGLE_BRANCH)
+                        .long("single-branch")
+                        .help("clone only the default branch")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_FILTER)
+                        .long("filter")
+                        .value_name("FILTER-SPEC")
+                        .help("partial clone filter, e.g. blob:none")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new(ARG_UNSHALLOW)
+                        .long("unshallow")
+                        .help("fetch the whole history of the shallow repositories")
+                        .conflicts_with(ARG_DEPTH)
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_CACHE)
+                        .long("cache")
+                        .help("clone the new repositories from a local cache of mirrors, kept up to date by every sync")
+                        .takes_value(false),
+                )
+                .arg(&arg_cache_dir)
+                .arg(
+                    Arg::new(ARG_UPDATE_BRANCHES)
+                        .long("update-branches")
+                        .help("fast-forward the checked out branch to its upstream after fetching")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_ALL_BRANCHES)
+                        .long("all-branches")
+                        .help("also fast-forward all the other tracking branches")
+                        .requires(ARG_UPDATE_BRANCHES)
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_REBASE)
+                        .long("rebase")
+                        .help("rebase the checked out branch if it has local commits")
+                        .requires(ARG_UPDATE_BRANCHES)
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_PRUNE)
+                        .long("prune")
+                        .help("remove the unmaintained repositories without local changes")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_PRUNE_DRY_RUN)
+                        .long("prune-dry-run")
+                        .help("list the unmaintained repositories that would be pruned")
+                        .conflicts_with_all(&[ARG_PRUNE, ARG_PRUNE_ARCHIVE])
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new(ARG_PRUNE_ARCHIVE)
+                        .long("prune-archive")
+                        .value_name("ARCHIVE-DIR")
+                        .help("move the prunable repositories to a directory instead of removing them")
+                        .conflicts_with(ARG_PRUNE)
+                        .takes_value(true),
+                ),
         );
 
     let mut help_bytes = Vec::new();
     app.clone().write_help(&mut help_bytes).unwrap();
     let help = String::from_utf8(help_bytes).expect("help is utf8");
 
+    let completion_app = app.clone();
     let matches = app.get_matches();
     let git_exec = matches.is_present(ARG_GIT_EXEC);
+    let offline = matches.is_present(ARG_OFFLINE);
     let skip_load = matches.is_present(ARG_INIT_SKIP_LOAD);
     let manifests = if skip_load { None } else { init().unwrap() };
 
     let mut app_params = AppParams {
         git_exec,
+        offline,
         sys_manifests: std::sync::Arc::new(manifests),
         manifest_file: None,
         manifest_selector: None,
         manifest_dest: None,
+        remote: None,
+        topo: false,
     };
 
+    let cache_dir = |m: &clap::ArgMatches| match m.value_of(ARG_CACHE_DIR) {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => mugit_dir().join("cache"),
+    };
+
+    fn set_manifest_options(app_params: &mut AppParams, m: &clap::ArgMatches) {
+        app_params.manifest_file = m.value_of(ARG_MANIFEST_FILE).map(|x| x.into());
+        app_params.manifest_dest = m.value_of(ARG_MANIFEST_DEST).map(|x| x.into());
+        app_params.manifest_selector = m.value_of(ARG_PROJECT).map(|x| x.to_owned());
+    }
+
     // helper commands unrelated to the main tool which is about multiple gits
     if let Some(m) = matches.subcommand_matches(SUBCMD_VERSION_FIND) {
         let repo_path = m.value_of(ARG_REPO).unwrap();
@@ -287,20 +1071,64 @@ fn main() {
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_DEBUG) {
         let manifest_file = m.value_of(ARG_MANIFEST_FILE).unwrap();
         manifest_debug(manifest_file)
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_CACHE) {
+        if let Some(m) = m.subcommand_matches(SUBCMD_CACHE_GC) {
+            let days: u64 = m
+                .value_of(ARG_MAX_AGE)
+                .unwrap()
+                .parse()
+                .expect("max age is a number of days");
+            let max_age = std::time::Duration::from_secs(days * 24 * 60 * 60);
+            let dry_run = m.is_present(ARG_DRY_RUN);
+            cache::Cache::new(cache_dir(m))
+                .gc(max_age, dry_run)
+                .unwrap()
+        } else {
+            println!("error: no cache subcommand specified\n\n{}", help)
+        }
     }
-
-    fn set_manifest_options(app_params: &mut AppParams, m: &clap::ArgMatches) {
-        app_params.manifest_file = m.value_of(ARG_MANIFEST_FILE).map(|x| x.into());
-        app_params.manifest_dest = m.value_of(ARG_MANIFEST_DEST).map(|x| x.into());
-        app_params.manifest_selector = m.value_of(ARG_PROJECT).map(|x| x.to_owned());
-    }
-
     // multiple repositories commands
-    if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_SYNC) {
+    else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_SYNC) {
         set_manifest_options(&mut app_params, m);
-        manifest_sync(&app_params).unwrap()
+        let prune = if m.is_present(ARG_PRUNE_DRY_RUN) {
+            Some(Prune::DryRun)
+        } else if let Some(archive_dir) = m.value_of(ARG_PRUNE_ARCHIVE) {
+            Some(Prune::Archive(archive_dir.into()))
+        } else if m.is_present(ARG_PRUNE) {
+            Some(Prune::Remove)
+        } else {
+            None
+        };
+        let update_branches = if m.is_present(ARG_UPDATE_BRANCHES) {
+            Some(UpdateBranches {
+                all: m.is_present(ARG_ALL_BRANCHES),
+                rebase: m.is_present(ARG_REBASE),
+            })
+        } else {
+            None
+        };
+        let clone = CloneOptions {
+            depth: m
+                .value_of(ARG_DEPTH)
+                .map(|x| x.parse().expect("depth is a number")),
+            single_branch: m.is_present(ARG_SINGLE_BRANCH),
+            filter: m.value_of(ARG_FILTER).map(|x| x.to_owned()),
+        };
+        let options = SyncOptions {
+            prune,
+            update_branches,
+            clone,
+            unshallow: m.is_present(ARG_UNSHALLOW),
+            cache: if m.is_present(ARG_CACHE) {
+                Some(cache::Cache::new(cache_dir(m)))
+            } else {
+                None
+            },
+        };
+        manifest_sync(&app_params, options).unwrap()
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_HAS_BRANCH) {
         set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
         let branch = m.value_of(ARG_BRANCH).unwrap();
         manifest_has_branch(&app_params, branch).unwrap()
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_HAS_TAG) {
@@ -309,11 +1137,14 @@ fn main() {
         manifest_has_tag(&app_params, tag).unwrap()
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_SET_TAG) {
         set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
         let branch = m.value_of(ARG_BRANCH).unwrap();
         let or_branch = m.value_of(ARG_OR_BRANCH);
         let tag = m.value_of(ARG_TAG).unwrap();
         let skip_push = m.is_present(ARG_SKIP_PUSH);
         let continue_if_exists = m.is_present(ARG_CONTINUE_IF_EXISTS);
+        app_params.topo = m.is_present(ARG_TOPO);
+        let check_cargo_version = m.is_present(ARG_CHECK_CARGO_VERSION);
         manifest_set_tag(
             &app_params,
             branch,
@@ -321,28 +1152,252 @@ fn main() {
             skip_push,
             continue_if_exists,
             or_branch,
+            check_cargo_version,
         )
         .unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_CHECK_VERSIONS) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let branch = m.value_of(ARG_BRANCH).unwrap();
+        let or_branch = m.value_of(ARG_OR_BRANCH);
+        let tag = m.value_of(ARG_TAG).unwrap();
+        manifest_check_versions(&app_params, branch, or_branch, tag).unwrap()
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_SET_BRANCH) {
         set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
         let branch = m.value_of(ARG_BRANCH).unwrap();
         let commit = m.value_of(ARG_COMMIT).unwrap();
         let skip_push = m.is_present(ARG_SKIP_PUSH);
         let continue_if_exists = m.is_present(ARG_CONTINUE_IF_EXISTS);
         manifest_set_branch(&app_params, branch, commit, skip_push, continue_if_exists).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_CHECKOUT) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let branch = m.value_of(ARG_BRANCH).unwrap();
+        let or_branch = m.value_of(ARG_OR_BRANCH);
+        let create = m.is_present(ARG_CREATE);
+        let force = m.is_present(ARG_FORCE);
+        manifest_checkout(&app_params, branch, or_branch, create, force).unwrap()
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_HAS_CHANGE) {
         set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
         let tag = m.value_of(ARG_TAG).unwrap();
         let branch = m.value_of(ARG_BRANCH).unwrap();
         let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
         manifest_has_change(&app_params, tag, branch, continue_on_fail).unwrap()
     } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_CHANGELOG) {
         set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
         let rev1 = m.value_of(ARG_REV1).unwrap();
         let rev2 = m.value_of(ARG_REV2).unwrap();
         let show_no_diff = m.is_present(ARG_SHOW_NO_DIFF);
         let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
         manifest_changelog(&app_params, rev1, rev2, continue_on_fail, show_no_diff).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_ARCHIVE) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let rev = m.value_of(ARG_REV).unwrap();
+        let output = std::path::Path::new(m.value_of(ARG_OUTPUT).unwrap());
+        let submodules = m.is_present(ARG_SUBMODULES);
+        let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+        manifest_archive(&app_params, rev, output, submodules, continue_on_fail).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_BUNDLE) {
+        if let Some(m) = m.subcommand_matches(SUBCMD_BUNDLE_CREATE) {
+            set_manifest_options(&mut app_params, m);
+            app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+            let output = std::path::Path::new(m.value_of(ARG_BUNDLE_DIR).unwrap());
+            let since = m.value_of(ARG_SINCE);
+            let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+            manifest_bundle_create(&app_params, output, since, continue_on_fail).unwrap()
+        } else if let Some(m) = m.subcommand_matches(SUBCMD_BUNDLE_APPLY) {
+            set_manifest_options(&mut app_params, m);
+            let input = std::path::Path::new(m.value_of(ARG_BUNDLE_DIR).unwrap());
+            let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+            manifest_bundle_apply(&app_params, input, continue_on_fail).unwrap()
+        } else {
+            println!("error: no manifest-bundle subcommand specified\n\n{}", help)
+        }
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_MIRROR) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let to_prefix = m.value_of(ARG_TO).unwrap();
+        let refspecs = m
+            .values_of(ARG_REFSPEC)
+            .map(|v| v.map(|x| x.to_owned()).collect::<Vec<_>>());
+        let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+        manifest_mirror(
+            &app_params,
+            to_prefix,
+            refspecs.as_deref(),
+            continue_on_fail,
+        )
+        .unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_GREP) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let pattern = m.value_of(ARG_PATTERN).unwrap();
+        let pattern = if m.is_present(ARG_FIXED_STRINGS) {
+            regex::escape(pattern)
+        } else {
+            pattern.to_owned()
+        };
+        let regex = regex::bytes::RegexBuilder::new(&pattern)
+            .case_insensitive(m.is_present(ARG_IGNORE_CASE))
+            .build()
+            .expect("valid regular expression");
+        let paths = m
+            .values_of(ARG_PATH)
+            .map(|v| {
+                v.map(|x| glob::Pattern::new(x).expect("valid path pattern"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let jobs = match m.value_of(ARG_JOBS) {
+            Some(jobs) => jobs.parse().expect("jobs is a number"),
+            None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        let options = GrepOptions {
+            regex,
+            rev: m.value_of(ARG_REV).map(|x| x.to_owned()),
+            paths,
+            jobs,
+        };
+        manifest_grep(&app_params, &options).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_LOG) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let regex = |arg| {
+            m.value_of(arg)
+                .map(|x| regex::Regex::new(x).expect("valid regular expression"))
+        };
+        let date = |arg| {
+            m.value_of(arg)
+                .map(|x| util::parse_date(x).expect("date as YYYY-MM-DD"))
+        };
+        let options = LogOptions {
+            range: m.value_of(ARG_RANGE).map(|x| {
+                let (rev1, rev2) = x.split_once("..").expect("range as REV1..REV2");
+                (rev1.to_owned(), rev2.to_owned())
+            }),
+            message: regex(ARG_GREP),
+            author: regex(ARG_AUTHOR),
+            since: date(ARG_SINCE_DATE),
+            // until the end of that day
+            until: date(ARG_UNTIL_DATE).map(|x| x + 86400),
+            paths: m
+                .values_of(ARG_PATH)
+                .map(|v| v.map(|x| x.to_owned()).collect())
+                .unwrap_or_default(),
+            max_count: m
+                .value_of(ARG_MAX_COUNT)
+                .map(|x| x.parse().expect("max count is a number")),
+        };
+        let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+        manifest_log(&app_params, &options, continue_on_fail).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_START) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let topic = m.value_of(ARG_TOPIC).unwrap();
+        let repos = m.values_of(ARG_REPOS).unwrap().collect::<Vec<_>>();
+        let from = m.value_of(ARG_FROM);
+        manifest_start(&app_params, topic, &repos, from).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_PUSH_TOPIC) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let topic = m.value_of(ARG_TOPIC).unwrap();
+        let skip_push = m.is_present(ARG_SKIP_PUSH);
+        manifest_push_topic(&app_params, topic, skip_push).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_FINISH) {
+        set_manifest_options(&mut app_params, m);
+        let topic = m.value_of(ARG_TOPIC).unwrap();
+        let force = m.is_present(ARG_FORCE);
+        manifest_finish(&app_params, topic, force).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_STATUS) {
+        set_manifest_options(&mut app_params, m);
+        manifest_status(&app_params).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_COMMIT) {
+        set_manifest_options(&mut app_params, m);
+        let message = m.value_of(ARG_MESSAGE).unwrap();
+        let all = m.is_present(ARG_ALL);
+        let include_untracked = m.is_present(ARG_INCLUDE_UNTRACKED);
+        manifest_commit(&app_params, message, all, include_untracked).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_STASH) {
+        if let Some(m) = m.subcommand_matches(SUBCMD_STASH_PUSH) {
+            set_manifest_options(&mut app_params, m);
+            let message = m.value_of(ARG_MESSAGE);
+            let include_untracked = m.is_present(ARG_INCLUDE_UNTRACKED);
+            manifest_stash_push(&app_params, message, include_untracked).unwrap()
+        } else if let Some(m) = m.subcommand_matches(SUBCMD_STASH_POP) {
+            set_manifest_options(&mut app_params, m);
+            manifest_stash_pop(&app_params).unwrap()
+        } else if let Some(m) = m.subcommand_matches(SUBCMD_STASH_LIST) {
+            set_manifest_options(&mut app_params, m);
+            manifest_stash_list(&app_params).unwrap()
+        } else {
+            println!("error: no manifest-stash subcommand specified\n\n{}", help)
+        }
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_REPO) {
+        let repos =
+            manifest_repos::ManifestRepos::new(mugit_dir().join(manifest::MANIFEST_REPOS_DIR));
+        if let Some(m) = m.subcommand_matches(SUBCMD_MANIFEST_REPO_ADD) {
+            let url = m.value_of(ARG_URL).unwrap();
+            let name = m.value_of(ARG_NAME);
+            repos.add(&app_params, url, name).unwrap()
+        } else if let Some(m) = m.subcommand_matches(SUBCMD_MANIFEST_REPO_UPDATE) {
+            let name = m.value_of(ARG_NAME);
+            repos.update(&app_params, name).unwrap()
+        } else {
+            println!("error: no manifest-repo subcommand specified\n\n{}", help);
+        }
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_COMPLETIONS) {
+        let shell = m.value_of(ARG_SHELL).unwrap();
+        print!("{}", completion::completions(completion_app, shell))
+    } else if matches.subcommand_matches(SUBCMD_MAN).is_some() {
+        completion::man(completion_app).unwrap()
+    } else if matches
+        .subcommand_matches(SUBCMD_COMPLETE_PROJECTS)
+        .is_some()
+    {
+        if let Some(manifests) = app_params.sys_manifests.as_ref() {
+            for selector in manifests.selectors() {
+                println!("{}", selector)
+            }
+        }
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_TUI) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        tui::tui(&app_params).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_DIFF) {
+        set_manifest_options(&mut app_params, m);
+        app_params.remote = m.value_of(ARG_REMOTE).map(|x| x.to_owned());
+        let a = m.value_of(ARG_SIDE_A).unwrap();
+        let b = m.value_of(ARG_SIDE_B).unwrap();
+        let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+        manifest_diff(&app_params, a, b, continue_on_fail).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_BUMP_DEP) {
+        set_manifest_options(&mut app_params, m);
+        let krate = m.value_of(ARG_CRATE).unwrap();
+        let version = m.value_of(ARG_VERSION).unwrap();
+        let dry_run = m.is_present(ARG_DRY_RUN);
+        let commit_message = m.value_of(ARG_COMMIT_MESSAGE);
+        manifest_bump_dep(&app_params, krate, version, dry_run, commit_message).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_GRAPH) {
+        set_manifest_options(&mut app_params, m);
+        let format = match m.value_of(ARG_FORMAT) {
+            Some("dot") => GraphFormat::Dot,
+            _ => GraphFormat::Text,
+        };
+        manifest_graph(&app_params, format).unwrap()
+    } else if let Some(m) = matches.subcommand_matches(SUBCMD_MANIFEST_FOREACH) {
+        set_manifest_options(&mut app_params, m);
+        app_params.topo = m.is_present(ARG_TOPO);
+        let command = m
+            .values_of(ARG_COMMAND)
+            .unwrap()
+            .map(|x| x.to_owned())
+            .collect::<Vec<_>>();
+        let continue_on_fail = m.is_present(ARG_CONTINUE_ON_FAIL);
+        manifest_foreach(&app_params, &command, continue_on_fail).unwrap()
     } else if let Some(name) = matches.subcommand_name() {
         println!("error: unknown command {}\n\n{}", name, help)
     } else {
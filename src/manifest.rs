@@ -1,9 +1,8 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use thiserror::*;
-use toml;
 
 #[derive(Debug)]
 pub struct Manifests {
@@ -18,9 +17,74 @@ pub struct Manifest {
 #[derive(Clone, Debug, Deserialize)]
 pub struct Project {
     pub prefix: String,
-    pub repos: Vec<String>,
+    #[serde(deserialize_with = "deserialize_repos")]
+    pub repos: Vec<Repo>,
     #[serde(rename = "remote-name")]
     pub remote_name: String,
+    /// additional remotes of every repository, as name to url template
+    #[serde(default)]
+    pub remotes: BTreeMap<String, String>,
+}
+
+/// A repository of a project, written in the manifest either as a simple
+/// path string or as a table with the extra options
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Repo {
+    pub path: String,
+    /// checkout directory relative to the output directory,
+    /// by default the last component of the path
+    pub dest: Option<String>,
+    /// override the project prefix for this repository
+    pub prefix: Option<String>,
+    /// full url of the repository, taking precedence over any prefix
+    pub url: Option<String>,
+    /// override the project remote name for this repository
+    #[serde(rename = "remote-name")]
+    pub remote_name: Option<String>,
+    /// additional remotes, as name to url template, on top of the project ones
+    #[serde(default)]
+    pub remotes: BTreeMap<String, String>,
+    /// clone only this number of commits of history
+    pub depth: Option<u32>,
+    /// clone only the default branch
+    #[serde(rename = "single-branch", default)]
+    pub single_branch: bool,
+    /// partial clone filter (e.g. "blob:none"), which requires the git executable
+    pub filter: Option<String>,
+    /// submodules to leave alone, as paths relative to this repository
+    #[serde(rename = "skip-submodules", default)]
+    pub skip_submodules: Vec<String>,
+    /// submodules to checkout at a revision instead of the recorded commit,
+    /// as path relative to this repository to revision
+    #[serde(rename = "pin-submodules", default)]
+    pub pin_submodules: BTreeMap<String, String>,
+    /// other repositories of the project this one depends on, by path or checkout directory
+    #[serde(rename = "depends-on", default)]
+    pub depends_on: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RepoEntry {
+    Path(String),
+    Detailed(Box<Repo>),
+}
+
+fn deserialize_repos<'de, D>(deserializer: D) -> Result<Vec<Repo>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<RepoEntry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            RepoEntry::Path(path) => Repo {
+                path,
+                ..Default::default()
+            },
+            RepoEntry::Detailed(repo) => *repo,
+        })
+        .collect())
 }
 
 #[derive(Debug, Error)]
@@ -33,18 +97,156 @@ pub enum ManifestError {
 pub enum ProjectError {
     #[error("no project is defined in this manifest")]
     NoDefined,
-    #[error("cannot merge project due to prefix difference between {0} and {1}")]
-    CannotMergePrefix(String, String),
-    #[error("cannot merge project due to remote name difference between {0} and {1}")]
-    CannotMergeRemoteName(String, String),
     #[error("project {0} not found in this manifest")]
     CannotFind(String),
+    #[error("repository {0} has an invalid destination {1}")]
+    InvalidDestination(String, String),
+    #[error("repositories {0} and {1} have colliding destinations {2} and {3}")]
+    DestinationCollision(String, String, String, String),
+    #[error("repository {0} remote {1} cannot expand url template: {2}")]
+    RemoteTemplate(String, String, String),
 }
 
 impl Manifests {
     pub fn get(&self, name: &str) -> Option<&Manifest> {
         self.manifests.get(name)
     }
+
+    /// Get every valid project selector, `manifest` and `manifest::project`, sorted
+    pub fn selectors(&self) -> Vec<String> {
+        let mut selectors = Vec::new();
+        for (name, manifest) in self.manifests.iter() {
+            selectors.push(name.clone());
+            for project in manifest.projects.keys() {
+                selectors.push(format!("{}::{}", name, project));
+            }
+        }
+        selectors.sort();
+        selectors
+    }
+}
+
+impl Repo {
+    /// Get the prefix used for this repository and its relative submodules
+    pub fn prefix<'a>(&'a self, project: &'a Project) -> &'a str {
+        self.prefix.as_deref().unwrap_or(&project.prefix)
+    }
+
+    /// Get the url to clone this repository from
+    pub fn url(&self, project: &Project) -> String {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => format!("{}{}", self.prefix(project), self.path),
+        }
+    }
+
+    /// Get the name of the remote to fetch from and push to
+    pub fn remote_name<'a>(&'a self, project: &'a Project) -> &'a str {
+        self.remote_name.as_deref().unwrap_or(&project.remote_name)
+    }
+
+    /// Get all the remotes of this repository as name and url, starting with the main remote.
+    ///
+    /// The url templates can refer to `{user}` (from `MUGIT_USER` or `USER`),
+    /// `{repo}` (last component of the path), `{path}` and `{prefix}`.
+    /// A template named like the main remote is ignored.
+    pub fn remotes(&self, project: &Project) -> Result<Vec<(String, String)>, ProjectError> {
+        let main_remote = self.remote_name(project);
+        let mut templates = project.remotes.clone();
+        templates.extend(self.remotes.clone());
+
+        let mut out = vec![(main_remote.to_string(), self.url(project))];
+        for (name, template) in templates {
+            if name == main_remote {
+                continue;
+            }
+            let url = self
+                .expand_template(project, &template)
+                .map_err(|e| ProjectError::RemoteTemplate(self.path.clone(), name.clone(), e))?;
+            out.push((name, url))
+        }
+        Ok(out)
+    }
+
+    fn expand_template(&self, project: &Project, template: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unterminated variable in {}", template))?;
+            let var = &rest[start + 1..start + end];
+            let value = match var {
+                "user" => std::env::var("MUGIT_USER")
+                    .or_else(|_| std::env::var("USER"))
+                    .map_err(|_| "neither MUGIT_USER nor USER are set".to_string())?,
+                "repo" => Path::new(&self.path)
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or(&self.path)
+                    .to_string(),
+                "path" => self.path.clone(),
+                "prefix" => self.prefix(project).to_string(),
+                _ => return Err(format!("unknown variable {{{}}}", var)),
+            };
+            out.push_str(&value);
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Get the checkout directory of this repository, relative to the output directory
+    pub fn destination(&self) -> Result<PathBuf, ProjectError> {
+        let invalid = || {
+            ProjectError::InvalidDestination(
+                self.path.clone(),
+                self.dest.clone().unwrap_or_default(),
+            )
+        };
+        match &self.dest {
+            None => Path::new(&self.path)
+                .file_name()
+                .map(PathBuf::from)
+                .ok_or_else(invalid),
+            Some(dest) => {
+                let dest = Path::new(dest);
+                let valid = dest.components().count() > 0
+                    && dest.components().all(|c| matches!(c, Component::Normal(_)));
+                if valid {
+                    Ok(dest.to_path_buf())
+                } else {
+                    Err(invalid())
+                }
+            }
+        }
+    }
+}
+
+impl Project {
+    /// Get every repository along its checkout directory, relative to the output directory.
+    ///
+    /// Fails if two repositories would end up in the same directory,
+    /// or if one would be checked out inside another one.
+    pub fn destinations(&self) -> Result<Vec<(&Repo, PathBuf)>, ProjectError> {
+        let mut out: Vec<(&Repo, PathBuf)> = Vec::new();
+        for repo in self.repos.iter() {
+            let dest = repo.destination()?;
+            for (other, other_dest) in out.iter() {
+                if dest.starts_with(other_dest) || other_dest.starts_with(&dest) {
+                    return Err(ProjectError::DestinationCollision(
+                        other.path.clone(),
+                        repo.path.clone(),
+                        other_dest.display().to_string(),
+                        dest.display().to_string(),
+                    ));
+                }
+            }
+            out.push((repo, dest))
+        }
+        Ok(out)
+    }
 }
 
 impl Manifest {
@@ -54,28 +256,32 @@ impl Manifest {
         Ok(toml::from_str(&content).map_err(ManifestError::DeserializationError))
     }
 
+    /// Merge all the projects of this manifest into one project.
+    ///
+    /// The prefix, remote name and remotes of each project are set on its repositories,
+    /// so that projects with different prefixes or remotes can be merged.
     pub fn merge_project(&self) -> Result<Project, ProjectError> {
-        let mut projects_iter = self.projects.iter();
-        let (iname, mut overall_proj) = match projects_iter.next() {
+        let mut projects_iter = self.projects.values();
+        let mut overall_proj = match projects_iter.next() {
             None => Err(ProjectError::NoDefined),
-            Some((init_name, project)) => Ok((init_name, project.clone())),
+            Some(project) => Ok(Project {
+                repos: Vec::new(),
+                remotes: BTreeMap::new(),
+                ..project.clone()
+            }),
         }?;
 
-        for (proj_name, proj) in projects_iter {
-            if proj.prefix != overall_proj.prefix {
-                return Err(ProjectError::CannotMergePrefix(
-                    iname.clone(),
-                    proj_name.clone(),
-                ));
-            }
-            if proj.remote_name != overall_proj.remote_name {
-                return Err(ProjectError::CannotMergeRemoteName(
-                    iname.clone(),
-                    proj_name.clone(),
-                ));
-            }
-
-            overall_proj.repos.extend_from_slice(&proj.repos)
+        for proj in self.projects.values() {
+            overall_proj.repos.extend(proj.repos.iter().map(|repo| {
+                let mut remotes = proj.remotes.clone();
+                remotes.extend(repo.remotes.clone());
+                Repo {
+                    prefix: Some(repo.prefix(proj).to_string()),
+                    remote_name: Some(repo.remote_name(proj).to_string()),
+                    remotes,
+                    ..repo.clone()
+                }
+            }))
         }
         Ok(overall_proj)
     }
@@ -97,9 +303,16 @@ impl Manifest {
     }
 }
 
-pub(crate) fn read_manifests<P: AsRef<Path>>(p: P) -> Result<Manifests, std::io::Error> {
-    let mut known_files = HashMap::new();
-    for entry in std::fs::read_dir(p)? {
+/// Directory of the manifest repositories, in the mugit directory
+pub const MANIFEST_REPOS_DIR: &str = "repos";
+
+/// Read the valid manifests of a directory, named after their file with an optional namespace
+fn read_manifest_dir(
+    dir: &Path,
+    namespace: Option<&str>,
+    known_files: &mut HashMap<String, Manifest>,
+) -> Result<(), std::io::Error> {
+    for entry in std::fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
         match path.file_name() {
@@ -110,8 +323,11 @@ pub(crate) fn read_manifests<P: AsRef<Path>>(p: P) -> Result<Manifests, std::io:
                     if let Some(name) = s.strip_suffix(".toml") {
                         match Manifest::from_file(&path)? {
                             Ok(m) => {
-                                let _: Option<_> = known_files.insert(name.to_string(), m);
-                                ()
+                                let name = match namespace {
+                                    None => name.to_string(),
+                                    Some(namespace) => format!("{}/{}", namespace, name),
+                                };
+                                let _: Option<_> = known_files.insert(name, m);
                             }
                             Err(_) => continue,
                         }
@@ -122,6 +338,24 @@ pub(crate) fn read_manifests<P: AsRef<Path>>(p: P) -> Result<Manifests, std::io:
             },
         }
     }
+    Ok(())
+}
+
+/// Read the manifests of the mugit directory, and the ones of the manifest
+/// repositories as `repository/manifest`
+pub(crate) fn read_manifests<P: AsRef<Path>>(p: P) -> Result<Manifests, std::io::Error> {
+    let mut known_files = HashMap::new();
+    read_manifest_dir(p.as_ref(), None, &mut known_files)?;
+
+    let repos_dir = p.as_ref().join(MANIFEST_REPOS_DIR);
+    if repos_dir.is_dir() {
+        for entry in std::fs::read_dir(repos_dir)? {
+            let path = entry?.path();
+            if let (true, Some(name)) = (path.is_dir(), path.file_name().and_then(|n| n.to_str())) {
+                read_manifest_dir(&path, Some(name), &mut known_files)?;
+            }
+        }
+    }
     Ok(Manifests {
         manifests: known_files,
     })
@@ -0,0 +1,184 @@
+use git2::Repository;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::commands::{git_fetch, AppParams, FetchDepth};
+use super::githelp;
+
+/// Directory of git repositories holding manifests, one per directory
+pub struct ManifestRepos {
+    dir: PathBuf,
+}
+
+/// Count the manifest files at the top of a manifest repository
+fn manifest_count(path: &Path) -> usize {
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "toml"))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Names are directories of the manifest repositories directory, and prefix the
+/// manifest names with `/`, as in `repo/manifest::project`
+fn check_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.contains('/') || name.contains("::") || name.starts_with('.') {
+        anyhow::bail!("invalid manifest repository name {:?}", name)
+    }
+    Ok(())
+}
+
+impl ManifestRepos {
+    pub fn new(dir: PathBuf) -> Self {
+        ManifestRepos { dir }
+    }
+
+    /// Clone a manifest repository, named after the last component of its url by default
+    pub fn add(&self, app_params: &AppParams, url: &str, name: Option<&str>) -> anyhow::Result<()> {
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let url = url.trim_end_matches('/');
+                let last = url.rsplit(['/', ':']).next().unwrap_or(url);
+                last.strip_suffix(".git").unwrap_or(last)
+            }
+        };
+        check_name(name)?;
+        let path = self.dir.join(name);
+        if path.exists() {
+            anyhow::bail!(
+                "manifest repository {} already exists, use manifest-repo update",
+                name
+            )
+        }
+
+        if app_params.offline {
+            anyhow::bail!("offline, not cloning {}", url)
+        }
+
+        std::fs::create_dir_all(&self.dir)?;
+        if app_params.git_exec {
+            let out = Command::new("git")
+                .arg("clone")
+                .arg(url)
+                .arg(path.to_str().expect("mugit dir is utf8"))
+                .output()
+                .expect("git failed to start");
+            if !out.status.success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim())
+            }
+        } else {
+            let mut fo = git2::FetchOptions::new();
+            fo.remote_callbacks(githelp::remote_callbacks());
+            git2::build::RepoBuilder::new()
+                .fetch_options(fo)
+                .clone(url, &path)?;
+        }
+        println!(
+            "{:40} : {} with {} manifests",
+            name,
+            ansi_term::Color::Green.paint("added"),
+            manifest_count(&path)
+        );
+        Ok(())
+    }
+
+    /// Fetch and fast-forward the manifest repositories, or only one of them
+    pub fn update(&self, app_params: &AppParams, name: Option<&str>) -> anyhow::Result<()> {
+        let mut names = Vec::new();
+        match name {
+            Some(name) => {
+                check_name(name)?;
+                if !self.dir.join(name).is_dir() {
+                    anyhow::bail!("unknown manifest repository {}", name)
+                }
+                names.push(name.to_string())
+            }
+            None if !self.dir.is_dir() => {}
+            None => {
+                for entry in std::fs::read_dir(&self.dir)? {
+                    let path = entry?.path();
+                    if let (true, Some(name)) =
+                        (path.is_dir(), path.file_name().and_then(|n| n.to_str()))
+                    {
+                        names.push(name.to_string())
+                    }
+                }
+                names.sort();
+            }
+        }
+
+        let mut failed = 0;
+        for name in names {
+            let path = self.dir.join(&name);
+            let mut report_error = |s: &str| {
+                failed += 1;
+                println!("{:40} : {}", name, ansi_term::Color::Purple.paint(s))
+            };
+            let repo = match Repository::open(&path) {
+                Ok(repo) => repo,
+                Err(e) => {
+                    report_error(&format!("not a git repository: {}", e));
+                    continue;
+                }
+            };
+            if app_params.offline {
+                println!(
+                    "{:40} : {}",
+                    name,
+                    ansi_term::Color::Yellow.paint("offline, would fetch")
+                );
+                continue;
+            }
+            if let Err(e) = git_fetch(app_params, &repo, "origin", FetchDepth::Default) {
+                report_error(&format!("fetching failed: {}", e));
+                continue;
+            }
+            let branch = match githelp::current_branch(&repo) {
+                Some(branch) => branch,
+                None => {
+                    report_error("detached, not updating");
+                    continue;
+                }
+            };
+            let state = match githelp::upstream_state(&repo, &branch) {
+                Some(state) => state,
+                None => {
+                    report_error(&format!("branch {} has no upstream, not updating", branch));
+                    continue;
+                }
+            };
+            if state.behind == 0 {
+                println!(
+                    "{:40} : {}",
+                    name,
+                    ansi_term::Color::Green.paint("up to date")
+                );
+            } else if state.ahead > 0 {
+                report_error(&format!(
+                    "branch {} has local commits, not updating",
+                    branch
+                ));
+            } else if githelp::is_dirty(&repo) {
+                report_error("has local changes, not updating");
+            } else if let Err(e) = githelp::fast_forward(&repo, &branch, state.upstream, true) {
+                report_error(&format!("fast-forward failed: {}", e));
+            } else {
+                println!(
+                    "{:40} : {} {} commits, {} manifests",
+                    name,
+                    ansi_term::Color::Green.paint("updated"),
+                    state.behind,
+                    manifest_count(&path)
+                );
+            }
+        }
+        if failed > 0 {
+            anyhow::bail!("{} manifest repositories failed to update", failed)
+        }
+        Ok(())
+    }
+}
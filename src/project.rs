@@ -1,5 +1,6 @@
 use super::commands::AppParams;
-use super::manifest::{Manifest, Project};
+use super::graph::DepGraph;
+use super::manifest::{Manifest, Project, Repo};
 use std::path::{Path, PathBuf};
 
 /// Read the manifest file, do some basic checks and call a function with the right parameters
@@ -23,27 +24,23 @@ where
 
 /// Read the manifest file but also iterate over each repositories
 /// composing this project
-pub(crate) fn on_project_repos<F, R>(app_params: &AppParams, f: F) -> anyhow::Result<Vec<R>>
+pub(crate) fn on_project_repos<F, R>(app_params: &AppParams, mut f: F) -> anyhow::Result<Vec<R>>
 where
-    F: Fn(&Project, PathBuf, &str) -> anyhow::Result<R>,
+    F: FnMut(&Project, &Repo, PathBuf, &str) -> anyhow::Result<R>,
 {
     let (_manifest, project) = app_params.get_project();
     let out_dir = app_params.get_destpath();
 
+    let mut destinations = project.destinations()?;
+    if app_params.topo {
+        let order = DepGraph::build(&project, &out_dir)?.topo_order()?;
+        destinations
+            .sort_by_key(|(_, dest)| order.iter().position(|name| dest.to_str() == Some(name)));
+    }
+
     let mut returns = Vec::new();
-    for repo in project.repos.iter() {
-        let p = Path::new(repo);
-        let dest_repo = match p.file_name() {
-            None => {
-                println!("ignoring {}", repo);
-                continue;
-            }
-            Some(s) => {
-                let mut x = out_dir.to_path_buf();
-                x.push(s);
-                x
-            }
-        };
+    for (repo, dest) in destinations {
+        let dest_repo = out_dir.join(&dest);
 
         if !dest_repo.exists() {
             panic!(
@@ -52,13 +49,8 @@ where
             )
         }
 
-        let name = dest_repo
-            .file_name()
-            .expect("file name")
-            .to_str()
-            .expect("git with valid UTF8")
-            .to_string();
-        let r = f(&project, dest_repo, &name)?;
+        let name = dest.to_str().expect("git with valid UTF8").to_string();
+        let r = f(&project, repo, dest_repo, &name)?;
         returns.push(r)
     }
     Ok(returns)
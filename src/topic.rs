@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::commands::MUGIT_STATE_NAME;
+
+/// Name of the topics state file, in the mugit directory of the output directory
+const TOPICS_FILE_NAME: &str = "topics.toml";
+
+/// Topic branches spanning multiple repositories of an output directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Topics {
+    #[serde(default)]
+    pub topics: BTreeMap<String, Topic>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Topic {
+    /// upstream branch the topic was started from, by repository
+    pub repos: BTreeMap<String, String>,
+}
+
+impl Topics {
+    fn path(out_dir: &Path) -> PathBuf {
+        out_dir.join(MUGIT_STATE_NAME).join(TOPICS_FILE_NAME)
+    }
+
+    /// Read the topics of an output directory, none if there is no state file yet
+    pub fn load(out_dir: &Path) -> anyhow::Result<Topics> {
+        let path = Self::path(out_dir);
+        if !path.exists() {
+            return Ok(Topics::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save(&self, out_dir: &Path) -> anyhow::Result<()> {
+        let path = Self::path(out_dir);
+        std::fs::create_dir_all(path.parent().expect("state file has a parent"))?;
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Get the topics a repository is part of
+    pub fn topics_of(&self, repo: &str) -> Vec<&str> {
+        self.topics
+            .iter()
+            .filter(|(_, topic)| topic.repos.contains_key(repo))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let out_dir = std::env::temp_dir().join(format!("mugit-topics-{}", std::process::id()));
+        assert!(Topics::load(&out_dir).unwrap().topics.is_empty());
+
+        let mut topics = Topics::default();
+        for (name, repos) in [("fix", &["core"][..]), ("feature", &["core", "libs/utils"])] {
+            let topic = Topic {
+                repos: repos
+                    .iter()
+                    .map(|r| (r.to_string(), "origin/main".to_string()))
+                    .collect(),
+            };
+            topics.topics.insert(name.to_string(), topic);
+        }
+        topics.save(&out_dir).unwrap();
+
+        let loaded = Topics::load(&out_dir).unwrap();
+        assert_eq!(loaded.topics_of("core"), ["feature", "fix"]);
+        assert_eq!(loaded.topics_of("libs/utils"), ["feature"]);
+        assert!(loaded.topics_of("utils").is_empty());
+        assert_eq!(loaded.topics["fix"].repos["core"], "origin/main");
+        std::fs::remove_dir_all(out_dir).unwrap();
+    }
+}
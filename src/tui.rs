@@ -0,0 +1,339 @@
+use git2::{BranchType, Repository};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::PathBuf;
+
+use super::commands::{git_fetch, last_fetch_description, resolve_rev, AppParams, FetchDepth};
+use super::githelp;
+use super::project::on_project_repos;
+use super::util;
+
+/// State of a repository as shown in the dashboard
+struct RepoRow {
+    name: String,
+    dest_repo: PathBuf,
+    remote_name: String,
+    branch: Option<String>,
+    upstream: Option<githelp::UpstreamState>,
+    dirty: bool,
+    last_fetch: String,
+}
+
+impl RepoRow {
+    fn refresh(&mut self) {
+        let repo = Repository::open(&self.dest_repo).expect("git repository");
+        self.branch = githelp::current_branch(&repo);
+        self.upstream = self
+            .branch
+            .as_ref()
+            .and_then(|b| githelp::upstream_state(&repo, b));
+        self.dirty = githelp::is_dirty(&repo);
+        self.last_fetch = match githelp::last_fetch_time(&repo) {
+            None => "never".to_string(),
+            Some(t) => util::format_age(t),
+        };
+    }
+}
+
+/// What the text typed at the prompt is for
+enum Prompt {
+    Checkout,
+    Changelog,
+}
+
+struct Dashboard {
+    rows: Vec<RepoRow>,
+    state: TableState,
+    prompt: Option<(Prompt, String)>,
+    /// result of the last action, or the changelog
+    output: Vec<Line<'static>>,
+}
+
+impl Dashboard {
+    fn selected(&mut self) -> &mut RepoRow {
+        let index = self.state.selected().unwrap_or(0);
+        &mut self.rows[index]
+    }
+
+    fn message(&mut self, color: Color, s: String) {
+        self.output = vec![Line::styled(s, Style::default().fg(color))];
+    }
+
+    fn fetch(&mut self, app_params: &AppParams) {
+        let row = self.selected();
+        let repo = Repository::open(&row.dest_repo).expect("git repository");
+        if app_params.offline {
+            let name = row.name.clone();
+            return self.message(
+                Color::Yellow,
+                format!(
+                    "{}: offline, remote refs may be stale ({})",
+                    name,
+                    last_fetch_description(&repo)
+                ),
+            );
+        }
+        let result = git_fetch(app_params, &repo, &row.remote_name, FetchDepth::Default);
+        row.refresh();
+        let name = row.name.clone();
+        match result {
+            Ok(()) => self.message(Color::Green, format!("{}: fetched", name)),
+            Err(e) => self.message(Color::Magenta, format!("{}: fetching failed: {}", name, e)),
+        }
+    }
+
+    /// Checkout a local branch, or create it from the remote branch
+    fn checkout(&mut self, branch: &str) {
+        let row = self.selected();
+        let repo = Repository::open(&row.dest_repo).expect("git repository");
+        let result = if row.dirty {
+            Err("has local changes, not checking out".to_string())
+        } else if repo.find_branch(branch, BranchType::Local).is_ok() {
+            githelp::checkout_branch(&repo, branch, false).map_err(|e| e.to_string())
+        } else {
+            githelp::remote_resolve_branch(&repo, &row.remote_name, branch)
+                .map_err(|e| e.to_string())
+                .and_then(|commit| {
+                    let create = || -> Result<(), git2::Error> {
+                        let mut local = repo.branch(branch, &commit, false)?;
+                        local.set_upstream(Some(&format!("{}/{}", row.remote_name, branch)))?;
+                        githelp::checkout_branch(&repo, branch, false)
+                    };
+                    create().map_err(|e| e.to_string())
+                })
+        };
+        row.refresh();
+        let name = row.name.clone();
+        match result {
+            Ok(()) => self.message(Color::Green, format!("{}: checked out {}", name, branch)),
+            Err(e) => self.message(
+                Color::Magenta,
+                format!("{}: checkout of {} failed: {}", name, branch, e),
+            ),
+        }
+    }
+
+    /// Show the commits of `rev2` not in `rev1`, as manifest-changelog does
+    fn changelog(&mut self, revs: &str) {
+        let row = self.selected();
+        let name = row.name.clone();
+        let repo = Repository::open(&row.dest_repo).expect("git repository");
+        let (rev1, rev2) = match revs.split_whitespace().collect::<Vec<_>>()[..] {
+            [rev1, rev2] => (rev1, rev2),
+            _ => return self.message(Color::Magenta, "expected two revisions".to_string()),
+        };
+        let resolve = |rev: &str| {
+            resolve_rev(&repo, &row.remote_name, rev).ok_or(format!("revision {} is missing", rev))
+        };
+        let lines = resolve(rev1).and_then(|from| {
+            let to = resolve(rev2)?;
+            let walk = || -> Result<Vec<Line<'static>>, git2::Error> {
+                let mut revwalk = repo.revwalk()?;
+                revwalk.push(repo.find_object(to, None)?.peel_to_commit()?.id())?;
+                revwalk.hide(repo.find_object(from, None)?.peel_to_commit()?.id())?;
+                let mut lines = vec![Line::styled(
+                    format!("## differences for {} between {} and {}", name, rev1, rev2),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )];
+                for oid in revwalk {
+                    let commit = repo.find_commit(oid?)?;
+                    let time = std::time::UNIX_EPOCH
+                        + std::time::Duration::from_secs(commit.time().seconds().max(0) as u64);
+                    lines.push(Line::from(format!(
+                        "* {} ({}) {}",
+                        &commit.id().to_string()[..7],
+                        util::format_age(time),
+                        commit.summary().unwrap_or("")
+                    )));
+                }
+                Ok(lines)
+            };
+            walk().map_err(|e| e.to_string())
+        });
+        match lines {
+            Ok(lines) if lines.len() == 1 => {
+                self.message(Color::Green, format!("## no differences for {}", name))
+            }
+            Ok(lines) => self.output = lines,
+            Err(e) => self.message(Color::Magenta, format!("{}: {}", name, e)),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(5),
+                Constraint::Length(12),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+
+        let rows = self.rows.iter().map(|row| {
+            let branch = match &row.branch {
+                Some(branch) => Cell::from(branch.clone()).style(Style::default().fg(Color::Green)),
+                None => Cell::from("detached").style(Style::default().fg(Color::Yellow)),
+            };
+            let upstream = match &row.upstream {
+                Some(state) => {
+                    format!("{} +{} -{}", state.upstream_name, state.ahead, state.behind)
+                }
+                None => String::new(),
+            };
+            let dirty = if row.dirty {
+                Cell::from("dirty").style(Style::default().fg(Color::Red))
+            } else {
+                Cell::from("")
+            };
+            Row::new(vec![
+                Cell::from(row.name.clone()),
+                branch,
+                Cell::from(upstream),
+                dirty,
+                Cell::from(row.last_fetch.clone()),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(30),
+                Constraint::Percentage(20),
+                Constraint::Percentage(25),
+                Constraint::Length(6),
+                Constraint::Percentage(25),
+            ],
+        )
+        .header(
+            Row::new(vec!["repository", "branch", "upstream", "", "last fetch"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title("mugit"))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, chunks[0], &mut self.state);
+
+        let output =
+            Paragraph::new(self.output.clone()).block(Block::default().borders(Borders::ALL));
+        frame.render_widget(output, chunks[1]);
+
+        let help = match &self.prompt {
+            Some((Prompt::Checkout, input)) => format!("checkout branch: {}", input),
+            Some((Prompt::Changelog, input)) => format!("changelog between rev1 rev2: {}", input),
+            None => {
+                "j/k move  f fetch  c checkout  l changelog  s shell  r refresh  q quit".to_string()
+            }
+        };
+        frame.render_widget(Paragraph::new(help), chunks[2]);
+    }
+}
+
+/// Leave the dashboard for a shell in a repository, then come back
+fn run_shell(terminal: &mut DefaultTerminal, row: &RepoRow) -> std::io::Result<()> {
+    disable_raw_mode()?;
+    execute!(std::io::stdout(), LeaveAlternateScreen)?;
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    println!("{}: exit the shell to go back to mugit", row.name);
+    let _ = std::process::Command::new(shell)
+        .current_dir(&row.dest_repo)
+        .env("MUGIT_REPO", &row.name)
+        .status();
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    terminal.clear()
+}
+
+fn event_loop(
+    app_params: &AppParams,
+    terminal: &mut DefaultTerminal,
+    dashboard: &mut Dashboard,
+) -> anyhow::Result<()> {
+    loop {
+        terminal.draw(|frame| dashboard.draw(frame))?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+
+        if let Some((prompt, mut input)) = dashboard.prompt.take() {
+            match key.code {
+                KeyCode::Enter => match prompt {
+                    Prompt::Checkout => dashboard.checkout(input.trim()),
+                    Prompt::Changelog => dashboard.changelog(&input),
+                },
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    input.pop();
+                    dashboard.prompt = Some((prompt, input))
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    dashboard.prompt = Some((prompt, input))
+                }
+                _ => dashboard.prompt = Some((prompt, input)),
+            }
+            continue;
+        }
+
+        let last = dashboard.rows.len() - 1;
+        let selected = dashboard.state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => {
+                dashboard.state.select(Some((selected + 1).min(last)))
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                dashboard.state.select(Some(selected.saturating_sub(1)))
+            }
+            KeyCode::Char('f') => dashboard.fetch(app_params),
+            KeyCode::Char('c') => dashboard.prompt = Some((Prompt::Checkout, String::new())),
+            KeyCode::Char('l') => dashboard.prompt = Some((Prompt::Changelog, String::new())),
+            KeyCode::Char('s') => {
+                run_shell(terminal, dashboard.selected())?;
+                dashboard.selected().refresh()
+            }
+            KeyCode::Char('r') => {
+                dashboard.rows.iter_mut().for_each(|row| row.refresh());
+                dashboard.output.clear()
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Full screen dashboard of the repositories of the project
+pub fn tui(app_params: &AppParams) -> anyhow::Result<()> {
+    let rows = on_project_repos(app_params, |project, repo_entry, dest_repo, name| {
+        let mut row = RepoRow {
+            name: name.to_string(),
+            dest_repo,
+            remote_name: app_params.remote_name(project, repo_entry).to_string(),
+            branch: None,
+            upstream: None,
+            dirty: false,
+            last_fetch: String::new(),
+        };
+        row.refresh();
+        Ok(row)
+    })?;
+    if rows.is_empty() {
+        anyhow::bail!("no repository in the project")
+    }
+
+    let mut dashboard = Dashboard {
+        rows,
+        state: TableState::default().with_selected(Some(0)),
+        prompt: None,
+        output: Vec::new(),
+    };
+    let mut terminal = ratatui::init();
+    let result = event_loop(app_params, &mut terminal, &mut dashboard);
+    ratatui::restore();
+    result
+}
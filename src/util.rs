@@ -1,26 +1,205 @@
-use std::path::{Component, Path, PathBuf};
-
-pub fn path_canon(path: &Path) -> PathBuf {
-    let mut v = Vec::new();
-    for i in path.components() {
-        match i {
-            Component::Prefix(_) => {
-                panic!("should not have prefix")
-            }
-            Component::RootDir => {
-                panic!("should not have root dir")
+/// FNV-1a 64 bits hash, stable across runs and platforms
+pub fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Format the time elapsed since a point in time, e.g. "3 hours ago"
+pub fn format_age(time: std::time::SystemTime) -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    let (value, unit) = match secs {
+        0..=59 => (secs, "second"),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!(
+        "{} {}{} ago",
+        value,
+        unit,
+        if value == 1 { "" } else { "s" }
+    )
+}
+
+/// Resolve a submodule url against the url of its parent repository.
+///
+/// Only the urls starting with `./` or `../` are relative, any other url
+/// (absolute path, ssh, https, scp-like) is returned as-is.
+pub fn resolve_relative_url(base: &str, url: &str) -> String {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return url.to_string();
+    }
+    let mut out = base.trim_end_matches('/').to_string();
+    // scp-like urls (host:path) separate the host from the path with a colon,
+    // which has to be kept when `..` goes up to the host
+    let scp = !base.contains("://");
+    let mut separator = '/';
+    for component in url.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                let cut = if scp {
+                    out.rfind(['/', ':'])
+                } else {
+                    out.rfind('/')
+                };
+                let cut = cut.unwrap_or(0);
+                separator = if out[cut..].starts_with(':') {
+                    ':'
+                } else {
+                    '/'
+                };
+                out.truncate(cut);
             }
-            Component::CurDir => {}
-            Component::ParentDir => {
-                v.pop();
+            c => {
+                out.push(separator);
+                out.push_str(c);
+                separator = '/';
             }
-            Component::Normal(n) => v.push(n),
         }
     }
+    out
+}
+
+/// Convert days since the epoch to a (year, month, day) date, from Howard Hinnant's algorithm
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+/// Convert a (year, month, day) date to days since the epoch
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
 
-    let mut out = PathBuf::new();
-    for p in v {
-        out.push(p)
+/// Parse a YYYY-MM-DD date as seconds since the epoch, at midnight UTC
+pub fn parse_date(s: &str) -> Option<i64> {
+    let mut parts = s.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86400)
+}
+
+/// Format seconds since the epoch as a YYYY-MM-DD HH:MM UTC date
+pub fn format_date(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_url_scp() {
+        assert_eq!(
+            resolve_relative_url("git@host:org/repo", "../x"),
+            "git@host:org/x"
+        );
+        assert_eq!(
+            resolve_relative_url("git@host:org/repo", "../../x"),
+            "git@host:x"
+        );
+        assert_eq!(
+            resolve_relative_url("git@host:repo.git", "../x"),
+            "git@host:x"
+        );
+        assert_eq!(
+            resolve_relative_url("git@host:org/repo", "./x"),
+            "git@host:org/repo/x"
+        );
+    }
+
+    #[test]
+    fn relative_url_https() {
+        assert_eq!(
+            resolve_relative_url("https://host/org/repo.git", "../x.git"),
+            "https://host/org/x.git"
+        );
+        assert_eq!(
+            resolve_relative_url("https://host:8443/org/repo/", "../../y/x"),
+            "https://host:8443/y/x"
+        );
+    }
+
+    #[test]
+    fn relative_url_file() {
+        assert_eq!(
+            resolve_relative_url("/srv/team-a/core", "../utils"),
+            "/srv/team-a/utils"
+        );
+        assert_eq!(
+            resolve_relative_url("file:///srv/team-a/core", "../../team-b/utils"),
+            "file:///srv/team-b/utils"
+        );
+    }
+
+    #[test]
+    fn parse_dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(951868800));
+        assert_eq!(parse_date("2024-02-29"), Some(1709164800));
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("2024-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(format_date(0), "1970-01-01 00:00");
+        assert_eq!(
+            format_date(1709164800 + 13 * 3600 + 37 * 60),
+            "2024-02-29 13:37"
+        );
+        assert_eq!(format_date(-60), "1969-12-31 23:59");
+        for days in [-719468, -1, 0, 11016, 19782, 2932896] {
+            let date = format_date(days * 86400);
+            assert_eq!(parse_date(&date[..10]), Some(days * 86400), "{}", date);
+        }
+    }
+
+    #[test]
+    fn absolute_url_unchanged() {
+        assert_eq!(
+            resolve_relative_url("git@host:org/repo", "https://other/x"),
+            "https://other/x"
+        );
+        assert_eq!(
+            resolve_relative_url("/srv/core", "git@host:x"),
+            "git@host:x"
+        );
     }
-    out
 }
@@ -152,7 +152,7 @@ impl FromStr for Spec {
             return Err(ParseSpecError::Trailing(next.to_string()));
         }
 
-        let op = CompareOp::from_str(op_str).map_err(|s| ParseSpecError::InvalidOp(s))?;
+        let op = CompareOp::from_str(op_str).map_err(ParseSpecError::InvalidOp)?;
         let ver = VerSpec::from_str(ver_str)?;
         Ok(Self(op, ver))
     }
@@ -194,5 +194,5 @@ pub fn greater(v: &VerSpec, major: u64, minor: u64, patch: u64) -> bool {
 }
 
 pub fn eq(v: &VerSpec, major: u64, minor: u64, patch: u64) -> bool {
-    major == v.major && v.minor.map_or(true, |m| minor == m) && v.patch.map_or(true, |r| patch == r)
+    major == v.major && v.minor.is_none_or(|m| minor == m) && v.patch.is_none_or(|r| patch == r)
 }